@@ -1,14 +1,16 @@
 
 
 
-use crate::piece::PieceIndex;
+use crate::piece::{Piece, PieceIndex};
 use crate::position::{Color, Position};
-use crate::square::{Square};
+use crate::square::{between, Square};
 use crate::board::Bitboard;
+use crate::magic;
 
 
 
-// TODO this can be changes to insted use magic. Look up magic bitboard. I am to lacy to do it now.
+// The slider attacks (rook, bishop, queen) are looked up in the magic tables in magic.rs.
+// The ray versions below are used to build those tables, and to check them in the tests.
 
 
 
@@ -33,7 +35,17 @@ fn shift_attack(
 }
 
 
+#[inline]
 pub fn rook_attacks(square: Square, all_occ: Bitboard)-> Bitboard{
+    magic::rook_attacks(square, all_occ)
+}
+#[inline]
+pub fn bishop_attacks(square: Square, all_occ: Bitboard)-> Bitboard{
+    magic::bishop_attacks(square, all_occ)
+}
+
+// Slow, walks every ray one square at a time
+pub fn rook_attacks_rays(square: Square, all_occ: Bitboard)-> Bitboard{
     let original_square_mask = Bitboard::from(square.index());
     let (row, col) = Bitboard::index_to_coord(square.index());
 
@@ -45,7 +57,8 @@ pub fn rook_attacks(square: Square, all_occ: Bitboard)-> Bitboard{
     result_occ |= shift_attack(original_square_mask, col, all_occ, Bitboard::shift_left);
     result_occ
 }
-pub fn bishop_attacks(square: Square, all_occ: Bitboard)-> Bitboard{
+// Slow, walks every ray one square at a time
+pub fn bishop_attacks_rays(square: Square, all_occ: Bitboard)-> Bitboard{
     let original_square_mask = Bitboard::from(square.index());
     let (row, col) = Bitboard::index_to_coord(square.index());
 
@@ -115,7 +128,8 @@ pub fn knight_attacks(square: Square)-> Bitboard{
     }
     result_board
 }
-pub fn pawn_attacks(square: Square, all_occ: Bitboard, color : Color)-> Bitboard{
+// Only the pushes of a pawn (one square, or two from its first square), stopped by any piece in the way
+pub fn pawn_pushes(square: Square, all_occ: Bitboard, color : Color)-> Bitboard{
     let origin = Bitboard::from(square.index());
     let (row, _) = Bitboard::index_to_coord(square.index());
     let mut temp = origin;
@@ -131,13 +145,6 @@ pub fn pawn_attacks(square: Square, all_occ: Bitboard, color : Color)-> Bitboard
                     result_occ |= temp;
                 }
             }
-            temp = origin;
-            temp.shift_upp_right();
-            result_occ |= temp;
-            temp = origin;
-            temp.shift_upp_left();
-            result_occ |= temp;
-
         },
         Color::Black => {
             temp.shift_down();
@@ -149,23 +156,41 @@ pub fn pawn_attacks(square: Square, all_occ: Bitboard, color : Color)-> Bitboard
                     result_occ |= temp;
                 }
             }
+        }
+    };
+    result_occ
+}
+
+// Only the diagonal squares a pawn of `color` on `square` hits (no pushes), so this can be used for attack maps
+pub fn pawn_attacks(square: Square, color: Color)-> Bitboard{
+    let origin = Bitboard::from(square.index());
+    let mut result_occ = Bitboard::new_empty();
+    let mut temp = origin;
+    match color {
+        Color::White => {
+            temp.shift_upp_right();
+            result_occ |= temp;
             temp = origin;
+            temp.shift_upp_left();
+            result_occ |= temp;
+        },
+        Color::Black => {
             temp.shift_down_right();
             result_occ |= temp;
             temp = origin;
             temp.shift_down_left();
             result_occ |= temp;
-
         }
     };
     result_occ
 }
 
 
+// The squares the piece attacks (for pawns only the diagonals, see get_targets for the pushes as well)
 pub fn get_attacks(piece:PieceIndex, square: Square, all_occ: Bitboard, color : Color)-> Bitboard{
     match piece {
         PieceIndex::WhitePawn | PieceIndex::BlackPawn => {
-            pawn_attacks(square, all_occ, color)
+            pawn_attacks(square, color)
         }
         PieceIndex::WhiteKnight | PieceIndex::BlackKnight => {
             knight_attacks(square)
@@ -185,6 +210,98 @@ pub fn get_attacks(piece:PieceIndex, square: Square, all_occ: Bitboard, color :
     }
 }
 
+// The squares the piece may move to if they are empty or hold an enemy (pawns can push, but only capture diagonally)
+pub fn get_targets(piece:PieceIndex, square: Square, all_occ: Bitboard, color : Color)-> Bitboard{
+    match piece.to_piece() {
+        Piece::Pawn => pawn_pushes(square, all_occ, color) | pawn_attacks(square, color),
+        _ => get_attacks(piece, square, all_occ, color)
+    }
+}
+
+
+impl Position {
+    // Every piece (both colors) that attacks `square` given the occupancy `occupancy`.
+    // The occupancy is a parameter so that pieces can be "removed" without changing the position (x-rays, king walking along a ray)
+    pub fn attackers_to(&self, square: Square, occupancy: Bitboard) -> Bitboard{
+        let boards = &self.current.bitboards;
+
+        let knights = boards.get_bitboard(PieceIndex::WhiteKnight) | boards.get_bitboard(PieceIndex::BlackKnight);
+        let kings = boards.get_bitboard(PieceIndex::WhiteKing) | boards.get_bitboard(PieceIndex::BlackKing);
+        let queens = boards.get_bitboard(PieceIndex::WhiteQueen) | boards.get_bitboard(PieceIndex::BlackQueen);
+        let diagonal = boards.get_bitboard(PieceIndex::WhiteBishop) | boards.get_bitboard(PieceIndex::BlackBishop) | queens;
+        let straight = boards.get_bitboard(PieceIndex::WhiteRook) | boards.get_bitboard(PieceIndex::BlackRook) | queens;
+
+        // A white pawn attacks the square if a black pawn standing on the square would attack the white pawn (and the other way around)
+        let mut attackers = pawn_attacks(square, Color::Black) & boards.get_bitboard(PieceIndex::WhitePawn);
+        attackers |= pawn_attacks(square, Color::White) & boards.get_bitboard(PieceIndex::BlackPawn);
+        attackers |= knight_attacks(square) & knights;
+        attackers |= king_attacks(square) & kings;
+        attackers |= bishop_attacks(square, occupancy) & diagonal;
+        attackers |= rook_attacks(square, occupancy) & straight;
+        attackers
+    }
+
+    // Checks if any piece of color `by` attacks the square in the current position
+    pub fn is_square_attacked(&self, square: Square, by: Color) -> bool{
+        let by_occ = match by {
+            Color::White => self.current.bitboards.white_occupancy,
+            Color::Black => self.current.bitboards.black_occupancy
+        };
+        self.attackers_to(square, self.current.bitboards.all_occupancy).intersects(by_occ)
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<Square>{
+        let mut king_bb = self.current.bitboards.get_bitboard(PieceIndex::from_piece(Piece::King, color));
+        king_bb.pop_lsb().and_then(Square::from_idx)
+    }
+
+    // Is the side to move in check
+    pub fn is_in_check(&self) -> bool{
+        !self.checkers().is_empty()
+    }
+
+    // The pieces giving check to the side to move (empty when there is no king)
+    pub fn checkers(&self) -> Bitboard{
+        let color = self.current.side_to_move;
+        match self.king_square(color) {
+            Some(square) => self.attackers_to(square, self.current.bitboards.all_occupancy) & self.current.bitboards.occupancy(!color),
+            None => Bitboard::new_empty()
+        }
+    }
+
+    // The pieces of `color` that stand alone between their king and an enemy rook, bishop or queen,
+    // so they can only move along that line
+    pub fn pinned(&self, color: Color) -> Bitboard{
+        let boards = &self.current.bitboards;
+        let Some(king_square) = self.king_square(color) else { return Bitboard::new_empty() };
+        let them = boards.occupancy(!color);
+
+        // The enemy sliders that would attack the king if only their own pieces were on the board
+        let their_straight = boards.pieces(Piece::Rook, !color) | boards.pieces(Piece::Queen, !color);
+        let their_diagonal = boards.pieces(Piece::Bishop, !color) | boards.pieces(Piece::Queen, !color);
+        let snipers = (rook_attacks(king_square, them) & their_straight) | (bishop_attacks(king_square, them) & their_diagonal);
+
+        let mut pinned = Bitboard::new_empty();
+        for sniper in snipers {
+            let blockers = between(king_square, sniper) & boards.all_occupancy;
+            if blockers.count() == 1 && blockers.intersects(boards.occupancy(color)) {
+                pinned |= blockers;
+            }
+        }
+        pinned
+    }
+
+    // Every square a piece of `color` attacks (defended pieces of its own included, pawn pushes not)
+    pub fn attacked_squares(&self, color: Color) -> Bitboard{
+        let boards = &self.current.bitboards;
+        let mut attacked = Bitboard::new_empty();
+        for square in boards.occupancy(color) {
+            let piece = boards.piece_on_square(square).expect("attacked_squares: the occupancy says there is a piece here");
+            attacked |= get_attacks(piece, square, boards.all_occupancy, color);
+        }
+        attacked
+    }
+}
 
 
 
@@ -199,4 +316,79 @@ fn test_attacks(){
 
     dbg!(new_b);
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_is_square_attacked(){
+    let position = Position::new(Some("4k3/8/8/8/8/5n2/3p4/R3K2R w KQ - 0 1"));
+
+    assert!(position.is_square_attacked(Square::E1, Color::Black)); // the knight and the pawn
+    assert!(position.is_square_attacked(Square::C1, Color::Black)); // the pawn
+    assert!(position.is_square_attacked(Square::D2, Color::White)); // the king
+    assert!(!position.is_square_attacked(Square::D1, Color::Black)); // pawns don't attack forwards
+    assert!(!position.is_square_attacked(Square::F1, Color::Black));
+    assert_eq!(position.attackers_to(Square::E1, position.current.bitboards.all_occupancy),
+               Square::F3.to_bitboard() | Square::D2.to_bitboard() | Square::A1.to_bitboard() | Square::H1.to_bitboard()); // both colors
+}
+
+#[test]
+fn test_pawn_attacks(){
+    // Pushes and attacks are apart, and a blocked pawn still attacks
+    let occupancy = Square::E3.to_bitboard();
+    assert_eq!(pawn_attacks(Square::E2, Color::White), Square::D3.to_bitboard() | Square::F3.to_bitboard());
+    assert_eq!(pawn_pushes(Square::E2, Bitboard::new_empty(), Color::White), Square::E3.to_bitboard() | Square::E4.to_bitboard());
+    assert!(pawn_pushes(Square::E2, occupancy, Color::White).is_empty());
+    assert_eq!(pawn_attacks(Square::A7, Color::Black), Square::B6.to_bitboard());
+    assert_eq!(pawn_pushes(Square::A7, occupancy, Color::Black), Square::A6.to_bitboard() | Square::A5.to_bitboard());
+    assert_eq!(get_attacks(PieceIndex::WhitePawn, Square::E2, occupancy, Color::White), pawn_attacks(Square::E2, Color::White));
+    assert_eq!(get_targets(PieceIndex::WhitePawn, Square::E2, Bitboard::new_empty(), Color::White),
+               pawn_attacks(Square::E2, Color::White) | pawn_pushes(Square::E2, Bitboard::new_empty(), Color::White));
+}
+
+#[test]
+fn test_checkers_and_pins(){
+    // The rook on e8 checks, the bishop on b4 pins the knight on d2 and the queen on h4 is blocked by two pieces
+    let position = Position::new(Some("4r1k1/8/8/8/1b5q/6P1/3N1PP1/4K3 w - - 0 1"));
+    assert_eq!(position.checkers(), Square::E8.to_bitboard());
+    assert!(position.is_in_check());
+    assert_eq!(position.pinned(Color::White), Square::D2.to_bitboard());
+    assert!(position.pinned(Color::Black).is_empty());
+
+    // A check along the rank while the bishop is pinned on the file, and a double check
+    let position = Position::new(Some("4k3/8/8/8/4r3/8/4B3/r3K3 w - - 0 1"));
+    assert_eq!(position.checkers(), Square::A1.to_bitboard());
+    assert_eq!(position.pinned(Color::White), Square::E2.to_bitboard());
+    let position = Position::new(Some("4k3/8/8/8/4r3/3n4/8/4K3 w - - 0 1"));
+    assert_eq!(position.checkers(), Square::D3.to_bitboard() | Square::E4.to_bitboard());
+    assert!(position.pinned(Color::White).is_empty());
+
+    // Two pieces between the king and the rook, and a piece of the other color in between, are not pinned
+    let position = Position::new(Some("4k3/8/4r3/8/4P3/8/4B3/4K3 w - - 0 1"));
+    assert!(position.pinned(Color::White).is_empty());
+    let position = Position::new(Some("4k3/4r3/8/8/8/8/4n3/4K3 w - - 0 1"));
+    assert!(position.pinned(Color::White).is_empty());
+    assert!(position.pinned(Color::Black).is_empty());
+
+    let position = Position::new(None);
+    assert!(position.checkers().is_empty());
+    assert!(!position.is_in_check());
+    assert!(position.pinned(Color::White).is_empty());
+}
+
+#[test]
+fn test_attacked_squares(){
+    let position = Position::new(None);
+    // Pawns attack the third rank, knights and the pieces behind them reach the rest of the first two ranks
+    assert_eq!(position.attacked_squares(Color::White), (Bitboard::rank(1) | Bitboard::rank(2) | Bitboard::rank(0)) & !Square::A1.to_bitboard() & !Square::H1.to_bitboard());
+    assert_eq!(position.attacked_squares(Color::Black).count(), 22);
+
+    // The same as asking every square
+    let position = Position::new(Some("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"));
+    for color in [Color::White, Color::Black] {
+        let attacked = position.attacked_squares(color);
+        for idx in 0..64 {
+            let square = Square::from_idx(idx).unwrap();
+            assert_eq!(attacked.contains(square), position.is_square_attacked(square, color));
+        }
+    }
+}
@@ -22,6 +22,10 @@ use crate::board::Bitboard;
     pub const FILE_H: Bitboard = Bitboard::new_const(0x8080_8080_8080_8080);
 
 
+    pub const RANKS: [Bitboard; 8] = [RANK_1, RANK_2, RANK_3, RANK_4, RANK_5, RANK_6, RANK_7, RANK_8];
+    pub const FILES: [Bitboard; 8] = [FILE_A, FILE_B, FILE_C, FILE_D, FILE_E, FILE_F, FILE_G, FILE_H];
+
+
 // Diagonals
     // Diagonals oriented A1 → H8 (step = +9)
         pub const DIAG_A1_H8: Bitboard = Bitboard::new_const(0x8040_2010_0804_0201);
@@ -58,6 +62,8 @@ use crate::board::Bitboard;
     pub const CENTER_4: Bitboard = Bitboard::new_const(0x0000_0018_1800_0000); // The four central squares
     pub const BORDER: Bitboard = Bitboard::new_const(0xFF81_8181_8181_81FF); // The entire border of the board (upper, lower, and sides)
     pub const KINGS: Bitboard = Bitboard::new_const(0x1000000000000010); // only the king squares
+    pub const DARK_SQUARES: Bitboard = Bitboard::new_const(0xAA55_AA55_AA55_AA55); // A1 is dark
+    pub const LIGHT_SQUARES: Bitboard = Bitboard::new_const(0x55AA_55AA_55AA_55AA);
 
 
 
@@ -1,7 +1,8 @@
 use crate::position::Position;
-use crate::{piece::PieceIndex, position::Color};
+use crate::{piece::{Piece, PieceIndex}, position::Color};
 use crate::square::Square;
-use std::{fmt::Debug, ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not}};
+use crate::bitboard_consts::{FILES, RANKS};
+use std::{fmt::{self, Debug, Display}, ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Sub, SubAssign}};
 
 
 pub const ROWS: usize = 8;
@@ -15,7 +16,9 @@ pub struct Bitboards {
 
     pub white_occupancy: Bitboard,
     pub black_occupancy: Bitboard,
-    pub all_occupancy: Bitboard
+    pub all_occupancy: Bitboard,
+
+    pub mailbox: [Option<PieceIndex>; 64], // the piece on every square, kept in sync with boards by set and remove
 
 }
 
@@ -24,7 +27,7 @@ pub struct Bitboards {
 impl Bitboards{
     #[inline]
     pub fn new_empty() -> Self{
-        Bitboards { boards: [Bitboard(0);12], white_occupancy: Bitboard(0), black_occupancy: Bitboard(0), all_occupancy: Bitboard(0)}
+        Bitboards { boards: [Bitboard(0);12], white_occupancy: Bitboard(0), black_occupancy: Bitboard(0), all_occupancy: Bitboard(0), mailbox: [None; 64]}
     }
 
     // Get access to a bitboard (non mutable tho)
@@ -47,6 +50,7 @@ impl Bitboards{
             Color::White => self.white_occupancy |= square.to_bitboard()
         }
         self.all_occupancy   |= square.to_bitboard();
+        self.mailbox[square.index() as usize] = Some(piece);
     }
 
     pub fn remove(&mut self, piece: PieceIndex, square: Square){
@@ -55,10 +59,17 @@ impl Bitboards{
         self.all_occupancy &= all_but_square_mask;
         self.black_occupancy &= all_but_square_mask; // eg kan gjer dette med både kvit og svart da det alltid bare er ein av dei som kan vere 1
         self.white_occupancy &= all_but_square_mask;
+        self.mailbox[square.index() as usize] = None;
     }
 
-    // This is probably an expensive function, so don't use this to much
+    // This is probably an expensive function, so don't use this to much (also rebuilds the mailbox from the boards)
+    // Everything is built again from the piece boards, so pieces removed straight from `boards` are gone afterwards too
     pub fn uppdate_occupancy(&mut self){
+        self.white_occupancy = Bitboard(0);
+        self.black_occupancy = Bitboard(0);
+        self.all_occupancy = Bitboard(0);
+        self.mailbox = [None; 64];
+
         for (piece_nr, piece) in self.boards.into_iter().enumerate(){
             self.all_occupancy |= piece;
 
@@ -68,20 +79,40 @@ impl Bitboards{
             else{
                 self.black_occupancy |= piece;
             }
+
+            let piece_index = PieceIndex::try_from(piece_nr).expect("uppdate_occupancy: piece number outside of PieceIndex");
+            let mut squares = piece;
+            while let Some(idx) = squares.pop_lsb(){
+                self.mailbox[idx as usize] = Some(piece_index);
+            }
         }
     }
  
 
 
-    #[inline] // TODO this can be sped up by using mailbox: [Option<PieceIndex>; 64], which allways has the pieces for every square stored
+    #[inline]
     pub fn piece_on_square(&self, square: Square) -> Option<PieceIndex>{
+        self.mailbox[square.index() as usize]
+    }
 
-        for piece in 0..12{
-            if self.boards[piece].intersects(square.index().into()){
-                return Some(PieceIndex::try_from(piece).expect("cannot convert from piece to pieceindex in piece on square"));
-            }
+    // All the pieces of one type and color
+    #[inline]
+    pub fn pieces(&self, piece: Piece, color: Color) -> Bitboard{
+        self.boards[PieceIndex::from_piece(piece, color).index()]
+    }
+
+    #[inline]
+    pub fn occupancy(&self, color: Color) -> Bitboard{
+        match color {
+            Color::White => self.white_occupancy,
+            Color::Black => self.black_occupancy
         }
-        None
+    }
+
+    // Same as piece_on_square, but only finds pieces of the given color
+    #[inline]
+    pub fn piece_of_color_on_square(&self, square: Square, color: Color) -> Option<PieceIndex>{
+        self.piece_on_square(square).filter(|piece| piece.color() == color)
     }
 
 
@@ -104,11 +135,65 @@ impl Bitboard {
     }
 
 
+    #[inline]
+    pub fn from_square(square: Square) -> Self{
+        Bitboard(1u64 << square.index())
+    }
+
+    // The whole file (0 = a) or rank (0 = rank 1)
+    #[inline]
+    pub fn file(col: usize) -> Self{
+        FILES[col]
+    }
+    #[inline]
+    pub fn rank(row: usize) -> Self{
+        RANKS[row]
+    }
+
     #[inline]
     pub fn intersects(self, other: Self)-> bool{
         (self & other).0 != 0
     }
 
+    #[inline]
+    pub fn contains(self, square: Square) -> bool{
+        self.intersects(Self::from_square(square))
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool{
+        self.0 == 0
+    }
+
+    // Number of squares in the bitboard (popcount)
+    #[inline]
+    pub fn count(self) -> u32{
+        self.0.count_ones()
+    }
+
+    // The lowest square (a1 is lowest, h8 highest) without removing it
+    #[inline]
+    pub fn lsb(self) -> Option<Square>{
+        if self.0 == 0 {
+            return None;
+        }
+        Square::from_idx(self.0.trailing_zeros() as u8)
+    }
+
+    #[inline]
+    pub fn msb(self) -> Option<Square>{
+        if self.0 == 0 {
+            return None;
+        }
+        Square::from_idx(63 - self.0.leading_zeros() as u8)
+    }
+
+    // The squares from a1 to h8, "for square in bitboard" does the same
+    #[inline]
+    pub fn iter(self) -> BitboardIter{
+        BitboardIter(self)
+    }
+
     #[inline]
     pub fn set(&mut self, square_index: u8){
         self.0 |= 1<<square_index;
@@ -170,6 +255,12 @@ impl Bitboard {
         Self(val)
     }
 
+    // The raw number, for when you need to do math with it (like magic multiplication)
+    #[inline]
+    pub const fn to_u64(self)-> u64{
+        self.0
+    }
+
 }
 
 impl From<u64> for Bitboard {
@@ -212,11 +303,73 @@ impl Not for Bitboard {
         Self::from(!self.0)
     }
 }
-impl Debug for Bitboard{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl BitXor for Bitboard {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        Bitboard(self.0 ^ rhs.0)
+    }
+}
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0;
+    }
+}
+// The squares in self that are not in rhs
+impl Sub for Bitboard {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Bitboard(self.0 & !rhs.0)
+    }
+}
+impl SubAssign for Bitboard {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 &= !rhs.0;
+    }
+}
+
+pub struct BitboardIter(Bitboard);
+
+impl Iterator for BitboardIter {
+    type Item = Square;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_lsb().and_then(Square::from_idx)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count() as usize;
+        (count, Some(count))
+    }
+}
+impl ExactSizeIterator for BitboardIter {}
+
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = BitboardIter;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<Square> for Bitboard {
+    fn from_iter<T: IntoIterator<Item = Square>>(iter: T) -> Self {
+        iter.into_iter().fold(Bitboard::new_empty(), |bitboard, square| bitboard | Bitboard::from_square(square))
+    }
+}
 
+// Debug and Display print the same grid, Debug starts on a new line so it lines up in dbg!
+impl Debug for Bitboard{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f)?;
+        self.write_grid(f)
+    }
+}
+impl Display for Bitboard{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_grid(f)
+    }
+}
 
+impl Bitboard{
+    fn write_grid(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for row in (0..8).rev() {
             write!(f, "{}: ", row+1)?;
 
@@ -234,7 +387,6 @@ impl Debug for Bitboard{
         write!(f, "   A B C D E F G H")?;
         Ok(())
     }
-
 }
 
 
@@ -279,4 +431,76 @@ impl Debug for Bitboard{
 fn test_bitboard(){
     let pos = Position::new(None);
     dbg!(pos);
-}   
\ No newline at end of file
+}
+
+#[test]
+fn test_bitboard_api(){
+    let bitboard = Bitboard::from_square(Square::C4) | Bitboard::from_square(Square::A1) | Bitboard::from_square(Square::H8);
+    assert_eq!(bitboard.count(), 3);
+    assert!(!bitboard.is_empty());
+    assert!(Bitboard::new_empty().is_empty());
+    assert!(bitboard.contains(Square::C4));
+    assert!(!bitboard.contains(Square::C5));
+    assert_eq!(bitboard.lsb(), Some(Square::A1));
+    assert_eq!(bitboard.msb(), Some(Square::H8));
+    assert_eq!(Bitboard::new_empty().lsb(), None);
+    assert_eq!(bitboard.iter().collect::<Vec<_>>(), vec![Square::A1, Square::C4, Square::H8]);
+    assert_eq!(bitboard.iter().len(), 3);
+    assert_eq!(bitboard.into_iter().collect::<Bitboard>(), bitboard);
+
+    assert_eq!(bitboard ^ Bitboard::from_square(Square::A1), Bitboard::from_square(Square::C4) | Bitboard::from_square(Square::H8));
+    assert_eq!(bitboard - Bitboard::from_square(Square::C4), Bitboard::from_square(Square::A1) | Bitboard::from_square(Square::H8));
+    let mut changed = bitboard;
+    changed ^= bitboard;
+    assert!(changed.is_empty());
+    changed -= bitboard;
+    assert!(changed.is_empty());
+
+    assert_eq!(Bitboard::file(2), crate::bitboard_consts::FILE_C);
+    assert_eq!(Bitboard::rank(7), crate::bitboard_consts::RANK_8);
+    assert_eq!((Bitboard::file(2) & Bitboard::rank(3)).lsb(), Some(Square::C4));
+
+    let grid = Bitboard::rank(0).to_string();
+    assert_eq!(grid.lines().count(), 9);
+    assert_eq!(grid.lines().nth(7), Some("1: 1 1 1 1 1 1 1 1 "));
+    assert_eq!(format!("{:?}", Bitboard::rank(0)), format!("\n{}", grid));
+}
+
+#[test]
+fn test_mailbox_in_sync(){
+    use crate::test_util::{random_walk, test_rng};
+
+    fn slow_piece_on_square(bitboards: &Bitboards, square: Square) -> Option<PieceIndex>{
+        (0..12).map(|piece| PieceIndex::try_from(piece).unwrap())
+               .find(|piece| bitboards.get_bitboard(*piece).is_occupied(square))
+    }
+
+    let mut rng = test_rng();
+    let mut position = Position::new(Some("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"));
+    random_walk(&mut position, 100, &mut rng, |position| {
+        for idx in 0..64{
+            let square = Square::from_idx(idx).unwrap();
+            assert_eq!(position.current.bitboards.piece_on_square(square), slow_piece_on_square(&position.current.bitboards, square));
+        }
+    });
+
+    let bitboards = &position.current.bitboards;
+    assert_eq!(bitboards.pieces(Piece::King, Color::White), bitboards.get_bitboard(PieceIndex::WhiteKing));
+    assert_eq!(bitboards.occupancy(Color::Black), bitboards.black_occupancy);
+}   
+
+#[test]
+fn test_uppdate_occupancy(){
+    let mut bitboards = Position::new(None).current.bitboards;
+    // Pieces moved by hand, straight on the boards
+    bitboards.get_bitboard_mut(PieceIndex::WhitePawn).remove(Square::E2.index());
+    bitboards.get_bitboard_mut(PieceIndex::WhitePawn).set(Square::E4.index());
+    bitboards.get_bitboard_mut(PieceIndex::BlackQueen).remove(Square::D8.index());
+    bitboards.uppdate_occupancy();
+
+    assert_eq!(bitboards, Position::new(Some("rnb1kbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1")).current.bitboards);
+    assert_eq!(bitboards.piece_on_square(Square::E2), None);
+    assert_eq!(bitboards.piece_on_square(Square::D8), None);
+    assert!(!bitboards.all_occupancy.contains(Square::D8));
+    assert_eq!(bitboards.all_occupancy.count(), 31);
+}
@@ -0,0 +1,365 @@
+use std::fmt;
+
+use crate::board::Bitboards;
+use crate::fen_string::{back_rank_king, back_rank_rooks};
+use crate::kastling::{Castling, CastlingSide, Imposter};
+use crate::piece::{Piece, PieceIndex};
+use crate::position::{Color, Position, Snapshot};
+use crate::square::Square;
+
+
+
+// Setting up a position piece by piece (the set up position mode in the UI, puzzles).
+// The builder takes anything, build() checks that it is a position that can be played from:
+// one king each, no pawns on the first or last rank, the side that just moved is not in check,
+// a king and rook where every castling right needs them and an en passant square behind a pawn that just moved two squares.
+//
+//     let position = PositionBuilder::new()
+//         .place(PieceIndex::WhiteKing, Square::E1)
+//         .place(PieceIndex::WhiteRook, Square::H1)
+//         .place(PieceIndex::BlackKing, Square::E8)
+//         .castling(CastlingSide::WK, true)
+//         .build()?;
+
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionBuilder {
+    bitboards: Bitboards,
+    side_to_move: Color,
+    castling: [Option<Option<u8>>; 4], // indexed like CastlingSide::ALL, the rook file is found when building if it is not given
+    chess960: bool,
+    en_passant: Option<Square>,
+    halfmove_clock: u16,
+    fullmove_number: u16,
+}
+
+impl Default for PositionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PositionBuilder {
+    // An empty board with white to move
+    pub fn new() -> Self {
+        PositionBuilder {
+            bitboards: Bitboards::new_empty(),
+            side_to_move: Color::White,
+            castling: [None; 4],
+            chess960: false,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    // To edit a position (the history is not kept)
+    pub fn from_position(position: &Position) -> Self {
+        let current = &position.current;
+        let mut castling = [None; 4];
+        for (nr, side) in CastlingSide::ALL.into_iter().enumerate() {
+            if current.castling.can_castle(side) {
+                castling[nr] = Some(Some(current.castling.rook_file(side)));
+            }
+        }
+        PositionBuilder {
+            bitboards: current.bitboards,
+            side_to_move: current.side_to_move,
+            castling,
+            chess960: current.castling.chess960,
+            en_passant: current.en_passant,
+            halfmove_clock: current.halfmove_clock,
+            fullmove_number: current.fullmove_number,
+        }
+    }
+
+    // Puts the piece on the square, instead of what was there
+    pub fn place(&mut self, piece: PieceIndex, square: Square) -> &mut Self {
+        self.remove(square);
+        self.bitboards.set(piece, square);
+        self
+    }
+
+    pub fn remove(&mut self, square: Square) -> &mut Self {
+        if let Some(piece) = self.bitboards.piece_on_square(square) {
+            self.bitboards.remove(piece, square);
+        }
+        self
+    }
+
+    // Empties the board and takes away the castling rights and en passant square
+    pub fn clear(&mut self) -> &mut Self {
+        self.bitboards = Bitboards::new_empty();
+        self.castling = [None; 4];
+        self.en_passant = None;
+        self
+    }
+
+    pub fn piece_on_square(&self, square: Square) -> Option<PieceIndex> {
+        self.bitboards.piece_on_square(square)
+    }
+
+    pub fn side_to_move(&mut self, color: Color) -> &mut Self {
+        self.side_to_move = color;
+        self
+    }
+
+    // The rook is the outermost one on that side of the king, as with K and Q in a fen
+    pub fn castling(&mut self, side: CastlingSide, allowed: bool) -> &mut Self {
+        self.castling[castling_nr(side)] = allowed.then_some(None);
+        self
+    }
+
+    // For chess960, when it is not the outermost rook
+    pub fn castling_with_rook(&mut self, side: CastlingSide, rook_file: u8) -> &mut Self {
+        self.castling[castling_nr(side)] = Some(Some(rook_file));
+        self
+    }
+
+    // Chess960 is also found by build() when the king or a castling rook is not where it starts in normal chess
+    pub fn chess960(&mut self, chess960: bool) -> &mut Self {
+        self.chess960 = chess960;
+        self
+    }
+
+    pub fn en_passant(&mut self, square: Option<Square>) -> &mut Self {
+        self.en_passant = square;
+        self
+    }
+
+    pub fn halfmove_clock(&mut self, halfmove_clock: u16) -> &mut Self {
+        self.halfmove_clock = halfmove_clock;
+        self
+    }
+
+    pub fn fullmove_number(&mut self, fullmove_number: u16) -> &mut Self {
+        self.fullmove_number = fullmove_number;
+        self
+    }
+
+    pub fn build(&self) -> Result<Position, SetupError> {
+        let board = &self.bitboards;
+        check_pieces(board)?;
+
+        let castling = self.build_castling()?;
+        if let Some(square) = self.en_passant {
+            self.check_en_passant(square)?;
+        }
+
+        let mut current = Snapshot {
+            bitboards: *board,
+            side_to_move: self.side_to_move,
+            castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            zobrist_key: 0,
+        };
+        current.zobrist_key = current.compute_key();
+        let position = Position { current, history: vec![] };
+
+        // The side that just moved can't have left its king in check
+        let waiting = !self.side_to_move;
+        let king = position.king_square(waiting).expect("PositionBuilder::build: the kings are counted above");
+        if position.is_square_attacked(king, self.side_to_move) {
+            return Err(SetupError::OpponentInCheck(waiting));
+        }
+        Ok(position)
+    }
+
+    fn build_castling(&self) -> Result<Castling, SetupError> {
+        let mut castling = Castling::new();
+        castling.chess960 = self.chess960;
+
+        for side in CastlingSide::ALL {
+            let Some(rook_file) = self.castling[castling_nr(side)] else { continue };
+            let color = side.color();
+            let king = back_rank_king(&self.bitboards, color).ok_or(SetupError::CastlingWithoutKing(side))?;
+
+            let kingside = Imposter::from_castling_side(side) == Imposter::King;
+            let mut rooks = back_rank_rooks(&self.bitboards, color).into_iter().filter(|rook| if kingside { *rook > king } else { *rook < king });
+            let rook_file = match rook_file {
+                Some(file) => rooks.find(|rook| *rook == file),
+                None if kingside => rooks.max(),
+                None => rooks.min(),
+            }.ok_or(SetupError::CastlingWithoutRook(side))?;
+
+            castling.add_castle_right_with_rook(side, rook_file);
+            let standard_rook = if kingside { 7 } else { 0 };
+            if king != 4 || rook_file != standard_rook {
+                castling.chess960 = true;
+            }
+        }
+        Ok(castling)
+    }
+
+    // The en passant square is empty, and so is the square the pawn came from, and the pawn is in front of it
+    fn check_en_passant(&self, square: Square) -> Result<(), SetupError> {
+        let pawn_color = !self.side_to_move;
+        let (row, col) = square.to_coord();
+        let (ep_row, from_row, pawn_row) = match pawn_color {
+            Color::White => (2, 1, 3),
+            Color::Black => (5, 6, 4),
+        };
+        let empty = |row: usize| Square::from_coords(row, col).is_some_and(|square| self.bitboards.piece_on_square(square).is_none());
+        let pawn = Square::from_coords(pawn_row, col).and_then(|square| self.bitboards.piece_on_square(square));
+
+        if row != ep_row || !empty(ep_row) || !empty(from_row) || pawn != Some(PieceIndex::from_piece(Piece::Pawn, pawn_color)) {
+            return Err(SetupError::InvalidEnPassant(square));
+        }
+        Ok(())
+    }
+}
+
+// One king each and no pawns on the first or last rank, also used where positions are read from binary forms
+pub(crate) fn check_pieces(board: &Bitboards) -> Result<(), SetupError> {
+    for color in [Color::White, Color::Black] {
+        let kings = board.pieces(Piece::King, color).count();
+        if kings != 1 {
+            return Err(SetupError::KingCount(color, kings));
+        }
+    }
+
+    for square in (board.pieces(Piece::Pawn, Color::White) | board.pieces(Piece::Pawn, Color::Black)).iter() {
+        if matches!(square.to_coord().0, 0 | 7) {
+            return Err(SetupError::PawnOnBackRank(square));
+        }
+    }
+    Ok(())
+}
+
+fn castling_nr(side: CastlingSide) -> usize {
+    CastlingSide::ALL.iter().position(|other| *other == side).expect("castling_nr: ALL has every side")
+}
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetupError {
+    KingCount(Color, u32),          // the color and how many kings it has
+    PawnOnBackRank(Square),
+    OpponentInCheck(Color),         // the side that is not to move
+    CastlingWithoutKing(CastlingSide),
+    CastlingWithoutRook(CastlingSide),
+    InvalidEnPassant(Square),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::KingCount(color, count) => write!(f, "{:?} must have one king, not {}", color, count),
+            SetupError::PawnOnBackRank(square) => write!(f, "there is a pawn on {}, pawns can't be on the first or last rank", square.square_str()),
+            SetupError::OpponentInCheck(color) => write!(f, "{:?} is in check, but it is not {:?} to move", color, color),
+            SetupError::CastlingWithoutKing(side) => write!(f, "castling right {:?} without the king on its first rank", side),
+            SetupError::CastlingWithoutRook(side) => write!(f, "castling right {:?} without a rook to castle with", side),
+            SetupError::InvalidEnPassant(square) => write!(f, "{} can't be the en passant square, there is no pawn that just moved two squares past it", square.square_str()),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn kings() -> PositionBuilder {
+        let mut builder = PositionBuilder::new();
+        builder.place(PieceIndex::WhiteKing, Square::E1).place(PieceIndex::BlackKing, Square::E8);
+        builder
+    }
+
+    #[test]
+    fn test_build() {
+        let mut builder = PositionBuilder::new();
+        let back_rank = [Piece::Rook, Piece::Knight, Piece::Bishop, Piece::Queen, Piece::King, Piece::Bishop, Piece::Knight, Piece::Rook];
+        for (col, piece) in back_rank.into_iter().enumerate() {
+            builder.place(PieceIndex::from_piece(piece, Color::White), Square::from_coords(0, col).unwrap())
+                .place(PieceIndex::from_piece(Piece::Pawn, Color::White), Square::from_coords(1, col).unwrap())
+                .place(PieceIndex::from_piece(Piece::Pawn, Color::Black), Square::from_coords(6, col).unwrap())
+                .place(PieceIndex::from_piece(piece, Color::Black), Square::from_coords(7, col).unwrap());
+        }
+        for side in CastlingSide::ALL {
+            builder.castling(side, true);
+        }
+        let position = builder.build().unwrap();
+        assert_eq!(position.write_fen(), START_FEN);
+        assert_eq!(position.current, Position::new(None).current);
+
+        // Editing a position
+        let mut builder = PositionBuilder::from_position(&position);
+        builder.remove(Square::E2).place(PieceIndex::WhitePawn, Square::E4).place(PieceIndex::WhiteQueen, Square::D8)
+            .side_to_move(Color::Black).en_passant(Some(Square::E3)).castling(CastlingSide::BQ, false);
+        assert_eq!(builder.piece_on_square(Square::D8), Some(PieceIndex::WhiteQueen));
+        assert_eq!(builder.build().unwrap().write_fen(), "rnbQkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQk e3 0 1");
+
+        builder.clear();
+        assert_eq!(builder.build(), Err(SetupError::KingCount(Color::White, 0)));
+        let position = builder.place(PieceIndex::WhiteKing, Square::A1).place(PieceIndex::BlackKing, Square::H8)
+            .halfmove_clock(12).fullmove_number(40).build().unwrap();
+        assert_eq!(position.write_fen(), "7k/8/8/8/8/8/8/K7 b - - 12 40");
+
+        // Every fen that reads is the same position after a trip through the builder
+        for fen in [START_FEN, "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"] {
+            let position = Position::new(Some(fen));
+            assert_eq!(PositionBuilder::from_position(&position).build().unwrap().current, position.current);
+        }
+    }
+
+    #[test]
+    fn test_castling() {
+        // The outermost rook, and chess960 when the rooks or king are not where they are in normal chess
+        let mut builder = kings();
+        builder.place(PieceIndex::WhiteRook, Square::A1).place(PieceIndex::WhiteRook, Square::B1).place(PieceIndex::WhiteRook, Square::H1)
+            .castling(CastlingSide::WK, true).castling(CastlingSide::WQ, true);
+        let castling = builder.build().unwrap().current.castling;
+        assert_eq!((castling.rook_file(CastlingSide::WK), castling.rook_file(CastlingSide::WQ)), (7, 0));
+        assert!(!castling.chess960);
+
+        builder.castling_with_rook(CastlingSide::WQ, 1);
+        let castling = builder.build().unwrap().current.castling;
+        assert_eq!(castling.rook_file(CastlingSide::WQ), 1);
+        assert!(castling.chess960);
+
+        builder.castling_with_rook(CastlingSide::WQ, 7);
+        assert_eq!(builder.build(), Err(SetupError::CastlingWithoutRook(CastlingSide::WQ)));
+        builder.castling(CastlingSide::WQ, false).castling(CastlingSide::BK, true);
+        assert_eq!(builder.build(), Err(SetupError::CastlingWithoutRook(CastlingSide::BK)));
+        builder.castling(CastlingSide::BK, false).remove(Square::E1).place(PieceIndex::WhiteKing, Square::E2);
+        assert_eq!(builder.build(), Err(SetupError::CastlingWithoutKing(CastlingSide::WK)));
+
+        let position = kings().place(PieceIndex::WhiteRook, Square::H1).chess960(true).castling(CastlingSide::WK, true).build().unwrap();
+        assert!(position.is_chess960());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(kings().place(PieceIndex::WhiteKing, Square::A5).build(), Err(SetupError::KingCount(Color::White, 2)));
+        assert_eq!(kings().remove(Square::E8).build(), Err(SetupError::KingCount(Color::Black, 0)));
+        assert_eq!(kings().place(PieceIndex::BlackPawn, Square::C1).build(), Err(SetupError::PawnOnBackRank(Square::C1)));
+        assert_eq!(kings().place(PieceIndex::WhitePawn, Square::H8).build(), Err(SetupError::PawnOnBackRank(Square::H8)));
+
+        // Black is in check with white to move, the other way around is fine
+        let mut builder = kings();
+        builder.place(PieceIndex::WhiteRook, Square::E5);
+        assert_eq!(builder.build(), Err(SetupError::OpponentInCheck(Color::Black)));
+        assert!(builder.side_to_move(Color::Black).build().unwrap().is_in_check());
+
+        // After e2e4 the square is e3 and black is to move
+        let mut builder = kings();
+        builder.place(PieceIndex::WhitePawn, Square::E4).side_to_move(Color::Black).en_passant(Some(Square::E3));
+        assert!(builder.build().is_ok());
+        assert_eq!(builder.en_passant(Some(Square::D3)).build(), Err(SetupError::InvalidEnPassant(Square::D3)));
+        assert_eq!(builder.en_passant(Some(Square::E6)).build(), Err(SetupError::InvalidEnPassant(Square::E6)));
+        assert_eq!(builder.side_to_move(Color::White).en_passant(Some(Square::E3)).build(), Err(SetupError::InvalidEnPassant(Square::E3)));
+        assert_eq!(builder.side_to_move(Color::Black).place(PieceIndex::WhiteKnight, Square::E2).build(), Err(SetupError::InvalidEnPassant(Square::E3)));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::position::Position;
+
+
+
+// Chess960 (Fischer Random) start positions by their number (SP-ID, 0 to 959), numbered the way Scharnagl did it.
+// Number 518 is the normal start position.
+
+
+
+// Where the two knights go among the five squares that are still empty after the bishops and queen are placed
+const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+// The white back rank, from the a file to the h file ("RNBQKBNR" for 518)
+pub fn back_rank(id: u16) -> Option<[char; 8]> {
+    if id >= 960 {
+        return None;
+    }
+    let mut rank: [Option<char>; 8] = [None; 8];
+    let mut n = id as usize;
+
+    rank[n % 4 * 2 + 1] = Some('B'); // light square bishop on b, d, f or h
+    n /= 4;
+    rank[n % 4 * 2] = Some('B'); // dark square bishop on a, c, e or g
+    n /= 4;
+
+    let empty = |rank: &[Option<char>; 8]| (0..8).filter(|file| rank[*file].is_none()).collect::<Vec<_>>();
+
+    let queen = empty(&rank)[n % 6];
+    rank[queen] = Some('Q');
+    n /= 6;
+
+    let (first, second) = KNIGHT_PLACEMENTS[n];
+    let free = empty(&rank);
+    rank[free[first]] = Some('N');
+    rank[free[second]] = Some('N');
+
+    // The king always goes between the two rooks
+    for (file, piece) in empty(&rank).into_iter().zip(['R', 'K', 'R']) {
+        rank[file] = Some(piece);
+    }
+
+    Some(rank.map(|piece| piece.expect("back_rank: every square has been given a piece")))
+}
+
+pub fn start_fen(id: u16) -> Option<String> {
+    let white: String = back_rank(id)?.iter().collect();
+    let black = white.to_ascii_lowercase();
+    Some(format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", black, white))
+}
+
+impl Position {
+    pub fn chess960(id: u16) -> Option<Position> {
+        let fen = start_fen(id)?;
+        Some(Position::try_from_fen_960(&fen).expect("Position::chess960: start_fen always makes a valid fen"))
+    }
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::square::Square;
+
+    #[test]
+    fn test_start_positions() {
+        assert_eq!(start_fen(518).unwrap(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(back_rank(0).unwrap().iter().collect::<String>(), "BBQNNRKR");
+        assert_eq!(back_rank(959).unwrap().iter().collect::<String>(), "RKRNNQBB");
+        assert_eq!(back_rank(960), None);
+
+        let mut seen = std::collections::HashSet::new();
+        for id in 0..960 {
+            let rank = back_rank(id).unwrap();
+            assert!(seen.insert(rank), "{} is the same as an earlier position", id);
+
+            let bishops: Vec<usize> = (0..8).filter(|file| rank[*file] == 'B').collect();
+            assert_ne!(bishops[0] % 2, bishops[1] % 2); // one on each color
+            let rooks: Vec<usize> = (0..8).filter(|file| rank[*file] == 'R').collect();
+            let king = rank.iter().position(|piece| *piece == 'K').unwrap();
+            assert!(rooks[0] < king && king < rooks[1]);
+        }
+
+        let position = Position::chess960(518).unwrap();
+        assert!(position.is_chess960());
+        assert_eq!(position.write_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(position.write_shredder_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1");
+    }
+
+    #[test]
+    fn test_960_fen() {
+        // Shredder-FEN
+        let position = Position::try_from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+        assert!(position.is_chess960());
+        assert_eq!(position.write_shredder_fen(), "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+        assert_eq!(position.write_fen(), "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9");
+
+        // X-FEN with an inner rook: the white rook on b1 has the right, not the one on a1
+        let xfen = "rk2r3/8/8/8/8/8/8/RRK5 w Bk - 0 1";
+        let position = Position::try_from_fen(xfen).unwrap();
+        assert_eq!(position.current.castling.rook_file(crate::kastling::CastlingSide::WQ), 1);
+        assert_eq!(position.current.castling.rook_file(crate::kastling::CastlingSide::BK), 4);
+        assert_eq!(position.write_fen(), xfen);
+        assert_eq!(position.write_shredder_fen(), "rk2r3/8/8/8/8/8/8/RRK5 w Be - 0 1");
+
+        // a file letter without a rook on it
+        assert!(Position::try_from_fen("rk2r3/8/8/8/8/8/8/RRK5 w D - 0 1").is_err());
+        // normal fens are still normal chess
+        assert!(!Position::new(None).is_chess960());
+    }
+
+    #[test]
+    fn test_960_castling() {
+        // King takes rook: b1 to a1 is queen side castling, the king ends on c1 and the rook on d1
+        let mut position = Position::try_from_fen("4k3/8/8/8/8/8/8/RK4R1 w AG - 0 1").unwrap();
+        let castle = position.parse_uci("b1a1").unwrap();
+        assert_eq!(position.to_san(castle), "O-O-O");
+        position.make_move(castle);
+        assert_eq!(position.write_fen(), "4k3/8/8/8/8/8/8/2KR2R1 b - - 1 1");
+        position.unmake_move().unwrap();
+
+        // King side: the king goes to g1 where the rook stood, the rook to f1
+        let king_side = position.parse_san("O-O").unwrap();
+        assert_eq!(king_side.to_uci(), "b1g1");
+        position.make_move(king_side);
+        assert_eq!(position.write_fen(), "4k3/8/8/8/8/8/8/R4RK1 b - - 1 1");
+        position.unmake_move().unwrap();
+
+        // The king is not in check, but c1 is on the path to both sides
+        let attacked = Position::try_from_fen("2r1k3/8/8/8/8/8/8/RK4R1 w AG - 0 1").unwrap();
+        assert!(attacked.legal_moves().iter().all(|mov| mov.get_castle_side().is_none()));
+
+        // The castling rook hides an attack on the king's target square
+        let hidden = Position::try_from_fen("4k3/8/8/8/8/8/8/rR2K3 w B - 0 1").unwrap();
+        assert!(hidden.legal_moves().iter().all(|mov| mov.get_castle_side().is_none()));
+
+        // Moving a rook only loses the right of that rook
+        let mut rook_move = Position::try_from_fen("4k3/8/8/8/8/8/8/RK4R1 w AG - 0 1").unwrap();
+        rook_move.make_move(rook_move.parse_uci("g1g2").unwrap());
+        assert_eq!(rook_move.write_shredder_fen(), "4k3/8/8/8/8/8/6R1/RK6 b A - 1 1");
+    }
+
+    // https://www.chessprogramming.org/Chess960_Perft_Results
+    const PERFT_960: [(&str, &[u64]); 5] = [
+        ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189, 326672]),
+        ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002, 667366]),
+        ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471, 273318]),
+        ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", &[22, 593, 13440, 382958]),
+        ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", &[28, 1120, 31058, 1171749]),
+    ];
+
+    #[test]
+    fn test_perft_960() {
+        for (fen, counts) in PERFT_960 {
+            let mut position = Position::try_from_fen(fen).unwrap();
+            for (depth, expected) in counts.iter().enumerate() {
+                if cfg!(debug_assertions) && depth >= 3 { // the last one is slow in debug builds
+                    break;
+                }
+                assert_eq!(position.perft(depth as u32 + 1), *expected, "{} at depth {}\n{}", fen, depth + 1, position.perft_divide(depth as u32 + 1));
+            }
+        }
+
+        // Position 518 gives the normal numbers
+        let mut normal = Position::chess960(518).unwrap();
+        assert_eq!(normal.perft(3), 8902);
+        assert_eq!(normal.king_square(crate::position::Color::White), Some(Square::E1));
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+
+use crate::board::Bitboard;
+use crate::moves::BitMove;
+use crate::position::{Color, Position};
+use crate::square::Square;
+
+
+
+// Printing a position as a board, for the CLI, logs and test failures.
+// println!("{}", position) gives FEN letters and "{:#}" the unicode pieces, position.display(options) gives the rest:
+//
+//   8  r  n  b  q  k  b  n  r
+//   7  p  p  p  p  .  p  p  p
+//   6  .  .  .  .  .  .  .  .
+//   5  .  .  .  . [p] .  .  .
+//   4  .  .  .  .  P  .  .  .
+//   3  .  .  .  .  .  .  .  .
+//   2  P  P  P  P  .  P  P  P
+//   1  R  N  B  Q  K  B  N  R
+//      a  b  c  d  e  f  g  h
+//
+//   White to move, castling KQkq, en passant e6
+//   rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2
+//
+// The squares of the last move are in [ ] and a king in check in ( ).
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayOptions {
+    pub unicode: bool,             // ♔ instead of K
+    pub flipped: bool,             // black at the bottom
+    pub labels: bool,              // rank numbers and file letters
+    pub highlight: bool,           // the last move and a king in check
+    pub last_move: Option<BitMove>, // without it the last move is found from the history
+    pub footer: bool,              // side to move, castling, en passant and the fen
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions { unicode: false, flipped: false, labels: true, highlight: true, last_move: None, footer: true }
+    }
+}
+
+pub struct PositionDisplay<'a> {
+    position: &'a Position,
+    options: DisplayOptions,
+}
+
+impl Position {
+    pub fn display(&self, options: DisplayOptions) -> PositionDisplay<'_> {
+        PositionDisplay { position: self, options }
+    }
+
+    // The squares the side that moved last left or came to (both king and rook when castling)
+    fn last_move_squares(&self) -> Bitboard {
+        match self.history.last() {
+            Some(before) => {
+                let mover = before.side_to_move;
+                before.bitboards.occupancy(mover) ^ self.current.bitboards.occupancy(mover)
+            },
+            None => Bitboard::new_empty()
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = DisplayOptions { unicode: f.alternate(), ..DisplayOptions::default() };
+        write!(f, "{}", self.display(options))
+    }
+}
+
+impl fmt::Display for PositionDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = &self.options;
+        let current = &self.position.current;
+        let boards = &current.bitboards;
+
+        let (last_move, checked_king) = if options.highlight {
+            let last_move = match options.last_move {
+                Some(mov) => mov.get_start_square().to_bitboard() | mov.get_end_square().to_bitboard(),
+                None => self.position.last_move_squares()
+            };
+            let checked_king = if self.position.is_in_check() { self.position.king_square(current.side_to_move) } else { None };
+            (last_move, checked_king)
+        } else {
+            (Bitboard::new_empty(), None)
+        };
+
+        let rows: Vec<usize> = if options.flipped { (0..8).collect() } else { (0..8).rev().collect() };
+        let cols: Vec<usize> = if options.flipped { (0..8).rev().collect() } else { (0..8).collect() };
+
+        for row in &rows {
+            let mut line = String::new();
+            if options.labels {
+                line.push_str(&format!("{} ", row + 1));
+            }
+            for col in &cols {
+                let square = Square::from_coords(*row, *col).expect("PositionDisplay: rows and cols are 0..8");
+                let symbol = match boards.piece_on_square(square) {
+                    Some(piece) if options.unicode => piece.to_unicode(),
+                    Some(piece) => piece.to_fen_char(),
+                    None if options.unicode => '·',
+                    None => '.',
+                };
+                let (open, close) = if checked_king == Some(square) {
+                    ('(', ')')
+                } else if last_move.contains(square) {
+                    ('[', ']')
+                } else {
+                    (' ', ' ')
+                };
+                line.push(open);
+                line.push(symbol);
+                line.push(close);
+            }
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        if options.labels {
+            let files: String = cols.iter().map(|col| format!(" {} ", (b'a' + *col as u8) as char)).collect();
+            writeln!(f, "  {}", files.trim_end())?;
+        }
+
+        if options.footer {
+            let fen = if self.position.is_chess960() { self.position.write_shredder_fen() } else { self.position.write_fen() };
+            let fields: Vec<&str> = fen.split(' ').collect();
+            let side = if current.side_to_move == Color::White { "White" } else { "Black" };
+            let check = match (self.position.is_in_check(), self.position.legal_moves().size()) {
+                (true, 0) => " (checkmate)",
+                (true, _) => " (in check)",
+                _ => ""
+            };
+            writeln!(f)?;
+            writeln!(f, "{} to move{}, castling {}, en passant {}", side, check, fields[2], fields[3])?;
+            write!(f, "{}", fen)?;
+        }
+        Ok(())
+    }
+}
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_start_position() {
+        let position = Position::new(None);
+        let expected = "\
+8  r  n  b  q  k  b  n  r
+7  p  p  p  p  p  p  p  p
+6  .  .  .  .  .  .  .  .
+5  .  .  .  .  .  .  .  .
+4  .  .  .  .  .  .  .  .
+3  .  .  .  .  .  .  .  .
+2  P  P  P  P  P  P  P  P
+1  R  N  B  Q  K  B  N  R
+   a  b  c  d  e  f  g  h
+
+White to move, castling KQkq, en passant -
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(position.to_string(), expected);
+
+        let unicode = format!("{:#}", position);
+        assert_eq!(unicode.lines().next(), Some("8  ♜  ♞  ♝  ♛  ♚  ♝  ♞  ♜"));
+        assert_eq!(unicode.lines().nth(5), Some("3  ·  ·  ·  ·  ·  ·  ·  ·"));
+
+        // From black's side, and only the board
+        let options = DisplayOptions { flipped: true, labels: false, footer: false, ..DisplayOptions::default() };
+        let flipped = position.display(options).to_string();
+        assert_eq!(flipped.lines().count(), 8);
+        assert_eq!(flipped.lines().next(), Some(" R  N  B  K  Q  B  N  R"));
+        assert_eq!(flipped.lines().last(), Some(" r  n  b  k  q  b  n  r"));
+
+        let options = DisplayOptions { flipped: true, footer: false, ..DisplayOptions::default() };
+        let flipped = position.display(options).to_string();
+        assert_eq!(flipped.lines().next(), Some("1  R  N  B  K  Q  B  N  R"));
+        assert_eq!(flipped.lines().last(), Some("   h  g  f  e  d  c  b  a"));
+    }
+
+    #[test]
+    fn test_highlight() {
+        let mut position = Position::new(None);
+        for uci in ["e2e4", "e7e5"] {
+            position.make_move(position.parse_uci(uci).unwrap());
+        }
+        let text = position.to_string();
+        assert_eq!(text.lines().nth(1), Some("7  p  p  p  p [.] p  p  p"));
+        assert_eq!(text.lines().nth(3), Some("5  .  .  .  . [p] .  .  ."));
+        assert_eq!(text.lines().nth(4), Some("4  .  .  .  .  P  .  .  ."));
+        assert!(text.ends_with("White to move, castling KQkq, en passant e6\nrnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"));
+
+        // A given move, and no highlighting at all
+        let mov = position.parse_uci("g1f3").unwrap();
+        let text = position.display(DisplayOptions { last_move: Some(mov), ..DisplayOptions::default() }).to_string();
+        assert_eq!(text.lines().nth(7), Some("1  R  N  B  Q  K  B [N] R"));
+        assert_eq!(text.lines().nth(5), Some("3  .  .  .  .  . [.] .  ."));
+        let plain = position.display(DisplayOptions { highlight: false, ..DisplayOptions::default() }).to_string();
+        assert!(!plain.contains('['));
+
+        // Castling marks the king and the rook
+        let mut position = Position::new(Some("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        position.make_move(position.parse_uci("e1g1").unwrap());
+        assert_eq!(position.to_string().lines().nth(7), Some("1  R  .  .  . [.][R][K][.]"));
+    }
+
+    #[test]
+    fn test_check() {
+        let position = Position::new(Some("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"));
+        let text = position.to_string();
+        assert_eq!(text.lines().nth(7), Some("1  R  N  B  Q (K) B  N  R"));
+        assert!(text.contains("White to move (checkmate), castling KQkq, en passant -"));
+
+        let position = Position::new(Some("4k3/8/8/8/8/8/4R3/4K3 b - - 0 1"));
+        let text = position.display(DisplayOptions { flipped: true, ..DisplayOptions::default() }).to_string();
+        assert_eq!(text.lines().nth(7), Some("8  .  .  . (k) .  .  .  ."));
+        assert!(text.contains("Black to move (in check), castling -, en passant -"));
+    }
+}
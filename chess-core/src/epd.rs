@@ -0,0 +1,388 @@
+use std::fmt;
+
+use crate::fen_string::FenError;
+use crate::moves::BitMove;
+use crate::position::Position;
+use crate::san::SanError;
+
+
+
+// EPD (Extended Position Description), the line format test suites (WAC, STS, perft suites ...) are written in:
+// the first four FEN fields followed by operations, each an opcode with operands and ended by ';'
+// (the last ';' on a line may be left out, perft suites do that).
+// r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - bm Nb5; id "WAC.079";
+// The halfmove clock and fullmove number are taken from hmvc and fmvn when they are there.
+
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    BestMoves(Vec<BitMove>),          // bm
+    AvoidMoves(Vec<BitMove>),         // am
+    Id(String),                       // id
+    Comment(u8, String),              // c0 to c9
+    AnalysisDepth(u32),               // acd
+    CentipawnEval(i32),               // ce
+    PredictedVariation(Vec<BitMove>), // pv, each move is played after the one before it
+    Perft(u32, u64),                  // D1 to D6 (any Dn really), the depth and the number of leaf nodes
+    Other { opcode: String, operands: Vec<String> }, // kept as it was read, with the quotes taken off strings
+}
+
+impl Operation {
+    pub fn opcode(&self) -> String {
+        match self {
+            Operation::BestMoves(_) => "bm".to_string(),
+            Operation::AvoidMoves(_) => "am".to_string(),
+            Operation::Id(_) => "id".to_string(),
+            Operation::Comment(n, _) => format!("c{}", n),
+            Operation::AnalysisDepth(_) => "acd".to_string(),
+            Operation::CentipawnEval(_) => "ce".to_string(),
+            Operation::PredictedVariation(_) => "pv".to_string(),
+            Operation::Perft(depth, _) => format!("D{}", depth),
+            Operation::Other { opcode, .. } => opcode.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Epd {
+    pub position: Position,
+    pub operations: Vec<Operation>,
+}
+
+impl Epd {
+    pub fn parse(line: &str) -> Result<Epd, EpdError> {
+        let tokens = tokenize(line)?;
+        let mut tokens = tokens.into_iter();
+
+        let mut fields = Vec::with_capacity(4);
+        while fields.len() < 4 {
+            match tokens.next() {
+                Some(Token::Word(word)) => fields.push(word),
+                _ => return Err(EpdError::MissingField(fields.len())),
+            }
+        }
+
+        // Split the rest into operations
+        let mut raw_operations: Vec<(String, Vec<String>)> = Vec::new();
+        let mut current: Option<(String, Vec<String>)> = None;
+        for token in tokens {
+            match (token, &mut current) {
+                (Token::End, _) => raw_operations.extend(current.take()),
+                (Token::Word(word), None) => current = Some((word, Vec::new())),
+                (Token::Quoted(string), None) => return Err(EpdError::InvalidOpcode(string)),
+                (Token::Word(operand) | Token::Quoted(operand), Some((_, operands))) => operands.push(operand),
+            }
+        }
+        raw_operations.extend(current);
+
+        let clock = |opcode: &str, default: &str| raw_operations.iter()
+            .find(|(code, _)| code == opcode)
+            .and_then(|(_, operands)| operands.first().cloned())
+            .unwrap_or(default.to_string());
+        let fen = format!("{} {} {} {} {} {}", fields[0], fields[1], fields[2], fields[3], clock("hmvc", "0"), clock("fmvn", "1"));
+        let position = Position::try_from_fen(&fen).map_err(EpdError::InvalidFen)?;
+
+        let operations = raw_operations.into_iter()
+            .map(|(opcode, operands)| read_operation(&position, opcode, operands))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Epd { position, operations })
+    }
+
+    pub fn best_moves(&self) -> &[BitMove] {
+        self.operations.iter().find_map(|operation| match operation {
+            Operation::BestMoves(moves) => Some(moves.as_slice()),
+            _ => None
+        }).unwrap_or(&[])
+    }
+
+    pub fn avoid_moves(&self) -> &[BitMove] {
+        self.operations.iter().find_map(|operation| match operation {
+            Operation::AvoidMoves(moves) => Some(moves.as_slice()),
+            _ => None
+        }).unwrap_or(&[])
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operations.iter().find_map(|operation| match operation {
+            Operation::Id(id) => Some(id.as_str()),
+            _ => None
+        })
+    }
+
+    // (depth, nodes) for every Dn operation, in the order they were written
+    pub fn perft_counts(&self) -> Vec<(u32, u64)> {
+        self.operations.iter().filter_map(|operation| match operation {
+            Operation::Perft(depth, nodes) => Some((*depth, *nodes)),
+            _ => None
+        }).collect()
+    }
+
+    pub fn operation(&self, opcode: &str) -> Option<&Operation> {
+        self.operations.iter().find(|operation| operation.opcode() == opcode)
+    }
+
+    pub fn to_epd(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fen = self.position.write_fen();
+        let fields: Vec<&str> = fen.split_whitespace().take(4).collect();
+        write!(f, "{}", fields.join(" "))?;
+
+        for operation in &self.operations {
+            write!(f, " {}", operation.opcode())?;
+            match operation {
+                Operation::BestMoves(moves) | Operation::AvoidMoves(moves) => {
+                    for mov in moves {
+                        write!(f, " {}", self.position.to_san(*mov))?;
+                    }
+                },
+                Operation::PredictedVariation(moves) => {
+                    let mut position = self.position.clone();
+                    for mov in moves {
+                        write!(f, " {}", position.to_san(*mov))?;
+                        position.make_move(*mov);
+                    }
+                },
+                Operation::Id(string) | Operation::Comment(_, string) => write!(f, " \"{}\"", string)?,
+                Operation::AnalysisDepth(depth) => write!(f, " {}", depth)?,
+                Operation::CentipawnEval(eval) => write!(f, " {}", eval)?,
+                Operation::Perft(_, nodes) => write!(f, " {}", nodes)?,
+                Operation::Other { operands, .. } => {
+                    for operand in operands {
+                        if operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';') {
+                            write!(f, " \"{}\"", operand)?;
+                        } else {
+                            write!(f, " {}", operand)?;
+                        }
+                    }
+                },
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Epd {
+    type Err = EpdError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        Epd::parse(line)
+    }
+}
+
+// Every position in an EPD file with the line number (starting at 1) it was on.
+// Empty lines and lines starting with '#' are skipped.
+pub fn read_epd(text: &str) -> Vec<(usize, Result<Epd, EpdError>)> {
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| (index + 1, Epd::parse(line)))
+        .collect()
+}
+
+
+
+enum Token {
+    Word(String),
+    Quoted(String),
+    End, // ';'
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, EpdError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {},
+            ';' => tokens.push(Token::End),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+                tokens.push(Token::Quoted(string));
+            },
+            c => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.peek() {
+                    if next.is_whitespace() || *next == ';' || *next == '"' {
+                        break;
+                    }
+                    word.push(*next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn read_operation(position: &Position, opcode: String, operands: Vec<String>) -> Result<Operation, EpdError> {
+    let number = |operands: &[String]| -> Result<String, EpdError> {
+        match operands {
+            [operand] => Ok(operand.clone()),
+            _ => Err(EpdError::WrongOperandCount(opcode.clone())),
+        }
+    };
+    let invalid = |operand: String| EpdError::InvalidNumber { opcode: opcode.clone(), operand };
+    let san_moves = |position: &Position| -> Result<Vec<BitMove>, EpdError> {
+        operands.iter()
+            .map(|san| position.parse_san(san).map_err(|error| EpdError::IllegalMove { opcode: opcode.clone(), san: san.clone(), error }))
+            .collect()
+    };
+
+    let operation = match opcode.as_str() {
+        "bm" => Operation::BestMoves(san_moves(position)?),
+        "am" => Operation::AvoidMoves(san_moves(position)?),
+        "pv" => {
+            let mut line = position.clone();
+            let mut moves = Vec::with_capacity(operands.len());
+            for san in &operands {
+                let mov = line.parse_san(san).map_err(|error| EpdError::IllegalMove { opcode: opcode.clone(), san: san.clone(), error })?;
+                line.make_move(mov);
+                moves.push(mov);
+            }
+            Operation::PredictedVariation(moves)
+        },
+        "id" => Operation::Id(operands.join(" ")),
+        "acd" => {
+            let operand = number(&operands)?;
+            Operation::AnalysisDepth(operand.parse().map_err(|_| invalid(operand))?)
+        },
+        "ce" => {
+            let operand = number(&operands)?;
+            Operation::CentipawnEval(operand.parse().map_err(|_| invalid(operand))?)
+        },
+        code if is_comment(code) => Operation::Comment(code.as_bytes()[1] - b'0', operands.join(" ")),
+        code if is_perft(code) => {
+            let depth = code[1..].parse().map_err(|_| invalid(code[1..].to_string()))?;
+            let operand = number(&operands)?;
+            Operation::Perft(depth, operand.parse().map_err(|_| invalid(operand))?)
+        },
+        _ => Operation::Other { opcode, operands },
+    };
+    Ok(operation)
+}
+
+fn is_comment(opcode: &str) -> bool {
+    let bytes = opcode.as_bytes();
+    bytes.len() == 2 && bytes[0] == b'c' && bytes[1].is_ascii_digit()
+}
+
+fn is_perft(opcode: &str) -> bool {
+    opcode.len() > 1 && opcode.starts_with('D') && opcode[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EpdError {
+    MissingField(usize), // how many of the four position fields there were
+    InvalidFen(FenError),
+    InvalidOpcode(String),
+    UnterminatedString,
+    WrongOperandCount(String),
+    InvalidNumber { opcode: String, operand: String },
+    IllegalMove { opcode: String, san: String, error: SanError },
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpdError::MissingField(found) => write!(f, "an epd position needs 4 fields, found {}", found),
+            EpdError::InvalidFen(error) => write!(f, "invalid position: {}", error),
+            EpdError::InvalidOpcode(opcode) => write!(f, "'{}' is not an opcode", opcode),
+            EpdError::UnterminatedString => write!(f, "string operand is missing the closing '\"'"),
+            EpdError::WrongOperandCount(opcode) => write!(f, "operation '{}' takes exactly one operand", opcode),
+            EpdError::InvalidNumber { opcode, operand } => write!(f, "operation '{}': '{}' is not a valid number", opcode, operand),
+            EpdError::IllegalMove { opcode, san, error } => write!(f, "operation '{}', move '{}': {}", opcode, san, error),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_epd() {
+        let epd = Epd::parse("2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; c0 \"mate in 3; nice\";").unwrap();
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.best_moves(), &[epd.position.parse_uci("g3g6").unwrap()]);
+        assert_eq!(epd.operation("c0"), Some(&Operation::Comment(0, "mate in 3; nice".to_string())));
+        assert_eq!(epd.position.write_fen(), "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+
+        // Numbers, a principal variation, clocks and an opcode nobody knows
+        let epd = Epd::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - acd 12; ce -35; pv e4 e5 Nf3; hmvc 3; fmvn 7; xyz a \"b c\";").unwrap();
+        assert_eq!(epd.operation("acd"), Some(&Operation::AnalysisDepth(12)));
+        assert_eq!(epd.operation("ce"), Some(&Operation::CentipawnEval(-35)));
+        let Some(Operation::PredictedVariation(pv)) = epd.operation("pv") else { panic!("no pv") };
+        assert_eq!(pv.iter().map(|mov| mov.to_uci()).collect::<Vec<_>>(), ["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(epd.position.write_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3 7");
+        assert_eq!(epd.operation("xyz"), Some(&Operation::Other { opcode: "xyz".to_string(), operands: vec!["a".to_string(), "b c".to_string()] }));
+
+        // Perft suites put the ';' in front of the operations, so the last one has none
+        let epd = Epd::parse("4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 66 ;D3 1197").unwrap();
+        assert_eq!(epd.perft_counts(), [(1, 15), (2, 66), (3, 1197)]);
+        let epd = Epd::parse("4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 66 ;D3 1197;").unwrap();
+        assert_eq!(epd.perft_counts(), [(1, 15), (2, 66), (3, 1197)]);
+    }
+
+    #[test]
+    fn test_epd_errors() {
+        assert_eq!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w -"), Err(EpdError::MissingField(3)));
+        assert!(matches!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - e9 bm Kd1;"), Err(EpdError::InvalidFen(_))));
+        assert!(matches!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Ke3;"), Err(EpdError::IllegalMove { .. })));
+        assert!(matches!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - acd deep;"), Err(EpdError::InvalidNumber { .. })));
+        assert_eq!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - D99999999999 5;"),
+                   Err(EpdError::InvalidNumber { opcode: "D99999999999".to_string(), operand: "99999999999".to_string() }));
+        assert_eq!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - id \"x;"), Err(EpdError::UnterminatedString));
+    }
+
+    #[test]
+    fn test_write_epd() {
+        let line = "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - bm Nb5; am Nxc6 Nf5; id \"WAC.079\"; pv Nb5 O-O; D2 1234; sm \"odd one\";";
+        let epd = Epd::parse(line).unwrap();
+        assert_eq!(epd.to_epd(), line);
+        assert_eq!(epd.to_string().parse::<Epd>().unwrap(), epd);
+    }
+
+    // The suite perft.rs checks, but written the way it is shipped as an EPD file
+    const PERFT_EPD: &str = "
+# comments and empty lines are skipped
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ;D1 48 ;D2 2039
+8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 191 ;D3 2812
+";
+
+    #[test]
+    fn test_perft_epd() {
+        let positions = read_epd(PERFT_EPD);
+        assert_eq!(positions.iter().map(|(line, _)| *line).collect::<Vec<_>>(), [3, 4, 5]);
+        for (line, epd) in positions {
+            let mut epd = epd.unwrap();
+            for (depth, nodes) in epd.perft_counts() {
+                assert_eq!(epd.position.perft(depth), nodes, "line {} at depth {}", line, depth);
+            }
+        }
+    }
+}
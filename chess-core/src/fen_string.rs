@@ -1,121 +1,80 @@
 use crate::position::{Position, Color, Snapshot};
 
+use std::fmt;
 use std::str::FromStr;
 
 use crate::square::{Square};
 use crate::board::{Bitboards, Bitboard};
-use crate::piece::PieceIndex;
-use crate::kastling::{Castling, CastlingSide};
+use crate::piece::{Piece, PieceIndex};
+use crate::kastling::{Castling, CastlingSide, Imposter};
 
 impl Position{
 
+    // Panics if the fen string is not valid, use try_from_fen for fen from users or the network
     pub fn read_fen(fen_string: &str) -> Self {
-        // "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"   this is the starting fen string
-        let mut board = Bitboards::new_empty();
-        let mut side_to_move = Color::White;
-        let mut castling = Castling::new();
-        let mut en_passant = Some(Square::A1);
-        let mut halfmove_clock = 0;
-        let mut fullmove_clock = 0;
-
-        for (index, info) in fen_string.split_whitespace().enumerate(){
-            match index {
-                0 => { // handtere brett
-                    let rows = info.split('/');
-                    for (row_nr, row) in rows.enumerate(){
-                        let square_chars: Vec<char> = row.chars().collect();
-
-                        let mut str_idx: usize = 0;
-                        let mut col_nr: usize = 0;
-                        
-                        'rowy_loopy: while str_idx < square_chars.len() {
-
-
-                            if square_chars[str_idx].is_ascii_digit(){
-                                col_nr += square_chars[str_idx].to_digit(10).expect("read_fen: check said it should be a digit") as usize;
-                                str_idx += 1;
-                                continue 'rowy_loopy;
-                            }
-                            else{
-                                let piece_type = PieceIndex::from_fen_char(square_chars[str_idx]);
-                                match piece_type {
-                                    Some(piece) => board.set(piece, Square::from_coords(7-row_nr, col_nr).expect("read_fen generates a row and square that is not suposed to work")),
-                                    None => panic!("You can't have {} in the board-part of the fen representation.", square_chars[str_idx])
-                                }
-
-                            } 
-
-                            str_idx += 1;
-                            col_nr += 1;
-                        }                   
-                    }
-                    board.uppdate_occupancy();
-                }
-                
+        match Self::try_from_fen(fen_string) {
+            Ok(position) => position,
+            Err(error) => panic!("read_fen got an invalid fen string ({}): {}", fen_string, error)
+        }
+    }
 
+    // "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"   this is the starting fen string
+    // The two clocks can be left out (they are then 0 and 1), everything else must be there
+    pub fn try_from_fen(fen_string: &str) -> Result<Self, FenError> {
+        Self::read_fen_fields(fen_string, false)
+    }
 
-                1 => { // Handtere side som skal flytte
-                    match info {
-                        "w" | "W" => side_to_move = Color::White,
-                        "b" | "B" => side_to_move = Color::Black,
-                        _ => panic!("Invalid side to move in fen string: {}", info)
-                    }
-                }
+    fn read_fen_fields(fen_string: &str, chess960: bool) -> Result<Self, FenError> {
+        let mut fields = fen_string.split_whitespace();
 
+        let board = read_board(fields.next().ok_or(FenError::MissingField(FenField::Board))?)?;
 
-                2 => { // Handtere rokkering
 
-                    for kastle_side in info.chars().filter(|c| !c.is_whitespace()){
-                        match kastle_side {
-                            'K' => castling.add_castle_right(CastlingSide::WK),
-                            'Q' => castling.add_castle_right(CastlingSide::WQ),
-                            'k' => castling.add_castle_right(CastlingSide::BK),
-                            'q' => castling.add_castle_right(CastlingSide::BQ),
-                            '-' => (),
-                            _ => panic!("Rokkeringa må bare innehalde K, Q, k eller q, men du gav inn {}", kastle_side)
-                        }
-                    }
-                }
+        // Handtere side som skal flytte
+        let side_to_move = match fields.next().ok_or(FenError::MissingField(FenField::SideToMove))? {
+            "w" | "W" => Color::White,
+            "b" | "B" => Color::Black,
+            info => return Err(FenError::InvalidSideToMove(info.to_string()))
+        };
 
 
-                3 => { //Handtere en passant 
-                    match info {
-                        "-" => en_passant = None,
-                        _ =>  {
-                            match Square::from_str(info) {
-                                Ok(square) => en_passant = Some(square),
-                                Err(error_message) => panic!("Failed adding en passant to position: {}", error_message)
+        // Handtere rokkering (KQkq, X-FEN and Shredder-FEN, see read_castling)
+        let castling = read_castling(fields.next().ok_or(FenError::MissingField(FenField::Castling))?, &board, chess960)?;
 
-                            }
-                        }
-                        
-                        
-                    }
-                }
 
+        //Handtere en passant (the square must be behind a pawn the other side just moved two squares,
+        // so on rank 6 when white is to move and on rank 3 when black is)
+        let en_passant_row = if side_to_move == Color::White { 5 } else { 2 };
+        let en_passant = match fields.next().ok_or(FenError::MissingField(FenField::EnPassant))? {
+            "-" => None,
+            info => match Square::from_str(info) {
+                Ok(square) if square.to_coord().0 == en_passant_row => Some(square),
+                _ => return Err(FenError::InvalidEnPassant(info.to_string()))
+            }
+        };
 
-                4 => { // handtere halv trekk klokke, for 50 trekk regel.
-                    match info.parse::<u16>() {
-                        Ok(value) => halfmove_clock = value,
-                        Err(_) => panic!("Failed to parse halfmove clock as u16: {}", info),
-                    }
-                }
 
+        // handtere halv trekk klokke, for 50 trekk regel.
+        let halfmove_clock = match fields.next() {
+            Some(info) => info.parse::<u16>().map_err(|_| FenError::InvalidHalfmoveClock(info.to_string()))?,
+            None => 0
+        };
 
-                5 => { // Handtere heil trekk klokke, for å ... ej veit egentlig ikkje ka
-                    match info.parse::<u16>() {
-                        Ok(value) => fullmove_clock = value,
-                        Err(_) => panic!("Failed to parse fullmove clock as u16: {}", info),
-                    }
-                }
 
+        // Handtere heil trekk klokke, for å ... ej veit egentlig ikkje ka
+        let fullmove_number = match fields.next() {
+            Some(info) => info.parse::<u16>().map_err(|_| FenError::InvalidFullmoveNumber(info.to_string()))?,
+            None => 1
+        };
 
-                _ => {}
 
-            }
+        if let Some(extra) = fields.next() {
+            return Err(FenError::TooManyFields(extra.to_string()));
         }
-        
-        Position { current: Snapshot{bitboards: board, side_to_move: side_to_move, castling: castling, en_passant: en_passant, halfmove_clock: halfmove_clock, fullmove_number: fullmove_clock }, history: vec![]}
+
+        let mut current = Snapshot{bitboards: board, side_to_move, castling, en_passant, halfmove_clock, fullmove_number, zobrist_key: 0 };
+        current.zobrist_key = current.compute_key();
+        Ok(Position { current, history: vec![]})
     }
 
 
@@ -123,49 +82,31 @@ impl Position{
 
 
 
-    pub fn write_fen(&self) -> String {
-        let mut fen = String::new();
-        // Board
-        let mut no_piece_counter = 0;
-        let mut current_idx = Bitboard::coord_to_index(7, 0); // this will loop trough the board in the right order starting from upper left
-        
-        loop{
-            
-            let square = Square::from_idx(current_idx).expect("Square was not to find in the indexes i loop through to write fen board");
-            let piece_opt = self.current.bitboards.piece_on_square(square);
-
-            match piece_opt {
-                Some(piece) => {
-                    if no_piece_counter > 0{
-                        fen.push_str(&no_piece_counter.to_string());
-                    }
-                    fen.push(piece.to_fen_char());
-                    no_piece_counter = 0;
-                },
-                None => no_piece_counter += 1
-            }
+    // Reads the fen as a chess960 position, even if the pieces stand like in normal chess (position 518)
+    pub fn try_from_fen_960(fen_string: &str) -> Result<Self, FenError> {
+        let mut position = Self::read_fen_fields(fen_string, true)?;
+        position.current.castling.chess960 = true;
+        Ok(position)
+    }
 
+    pub fn is_chess960(&self) -> bool {
+        self.current.castling.chess960
+    }
 
 
-            if current_idx == 7{ // 7 is the last index
-                break;
-            }
 
-            let (row, col) = Bitboard::index_to_coord(current_idx);
-            if col < 7{
-                current_idx =  Bitboard::coord_to_index(row, col + 1);
-            }
-            else{
-                current_idx = Bitboard::coord_to_index(row - 1, 0);
-                if no_piece_counter > 0{
-                    fen.push_str(&no_piece_counter.to_string());
-                    no_piece_counter = 0;
-                }
-                fen.push('/');
-                
-            }
-        }
+    // Chess960 castling rights are written as X-FEN (KQkq when that is clear, the rook file if not)
+    pub fn write_fen(&self) -> String {
+        self.write_fen_with_castling(false)
+    }
 
+    // Castling rights always as rook files, "HAha" for the normal start position
+    pub fn write_shredder_fen(&self) -> String {
+        self.write_fen_with_castling(true)
+    }
+
+    fn write_fen_with_castling(&self, shredder: bool) -> String {
+        let mut fen = write_board(&self.current.bitboards);
         fen.push(' ');
 
 
@@ -175,10 +116,24 @@ impl Position{
         fen.push(' ');
 
         // rokkering
-        if self.current.castling.can_castle(CastlingSide::WK){ fen.push('K');}
-        if self.current.castling.can_castle(CastlingSide::WQ){ fen.push('Q');}
-        if self.current.castling.can_castle(CastlingSide::BK){ fen.push('k');}
-        if self.current.castling.can_castle(CastlingSide::BQ){ fen.push('q');}
+        let castling = self.current.castling;
+        for side in CastlingSide::ALL {
+            if !castling.can_castle(side) {
+                continue;
+            }
+            let rook_file = castling.rook_file(side);
+            let outermost = match Imposter::from_castling_side(side) {
+                Imposter::King => back_rank_rooks(&self.current.bitboards, side.color()).into_iter().max(),
+                Imposter::Queen => back_rank_rooks(&self.current.bitboards, side.color()).into_iter().min()
+            };
+            let letter = if !castling.chess960 || (!shredder && outermost == Some(rook_file)) {
+                if Imposter::from_castling_side(side) == Imposter::King { 'k' } else { 'q' }
+            } else {
+                (b'a' + rook_file) as char
+            };
+            fen.push(if side.color() == Color::White { letter.to_ascii_uppercase() } else { letter });
+        }
+        if castling.rights == 0{ fen.push('-');}
         fen.push(' ');
 
         // EN passant
@@ -211,16 +166,340 @@ impl Position{
 
 
 
+// The board field of a fen string
+pub(crate) fn write_board(board: &Bitboards) -> String {
+    let mut fen = String::new();
+    let mut no_piece_counter = 0;
+    let mut current_idx = Bitboard::coord_to_index(7, 0); // this will loop trough the board in the right order starting from upper left
+    
+    loop{
+        
+        let square = Square::from_idx(current_idx).expect("Square was not to find in the indexes i loop through to write fen board");
+        let piece_opt = board.piece_on_square(square);
+
+        match piece_opt {
+            Some(piece) => {
+                if no_piece_counter > 0{
+                    fen.push_str(&no_piece_counter.to_string());
+                }
+                fen.push(piece.to_fen_char());
+                no_piece_counter = 0;
+            },
+            None => no_piece_counter += 1
+        }
+
+
+
+        if current_idx == 7{ // 7 is the last index
+            if no_piece_counter > 0{
+                fen.push_str(&no_piece_counter.to_string());
+            }
+            break;
+        }
+
+        let (row, col) = Bitboard::index_to_coord(current_idx);
+        if col < 7{
+            current_idx =  Bitboard::coord_to_index(row, col + 1);
+        }
+        else{
+            current_idx = Bitboard::coord_to_index(row - 1, 0);
+            if no_piece_counter > 0{
+                fen.push_str(&no_piece_counter.to_string());
+                no_piece_counter = 0;
+            }
+            fen.push('/');
+            
+        }
+    }
+    fen
+}
+
+// The files of the rooks of that color on its back rank
+pub(crate) fn back_rank_rooks(board: &Bitboards, color: Color) -> Vec<u8> {
+    let row = if color == Color::White { 0 } else { 7 };
+    let mut rooks = board.pieces(Piece::Rook, color);
+    let mut files = Vec::new();
+    while let Some(idx) = rooks.pop_lsb() {
+        let (rook_row, rook_col) = Bitboard::index_to_coord(idx);
+        if rook_row == row {
+            files.push(rook_col as u8);
+        }
+    }
+    files
+}
+
+pub(crate) fn back_rank_king(board: &Bitboards, color: Color) -> Option<u8> {
+    let row = if color == Color::White { 0 } else { 7 };
+    let mut king = board.pieces(Piece::King, color);
+    let (king_row, king_col) = Bitboard::index_to_coord(king.pop_lsb()?);
+    (king_row == row).then_some(king_col as u8)
+}
+
+// KQkq as in normal chess, and for chess960:
+// X-FEN: K and Q mean the outermost rook on that side of the king, a file letter (like "Bg") is used when another rook is further out
+// Shredder-FEN: always the file letter of the rook ("HAha"), upper case for white
+// The position is a chess960 position if it is read as one, the file letters are used or the king is not on the e file.
+// With the king on e (or not on its first rank) in a normal fen, K and Q need the rook on h or a, a right without it is dropped.
+fn read_castling(info: &str, board: &Bitboards, chess960: bool) -> Result<Castling, FenError> {
+    let mut castling = Castling::new();
+    if info == "-" {
+        return Ok(castling);
+    }
+
+    for kastle_char in info.chars() {
+        let color = if kastle_char.is_ascii_uppercase() { Color::White } else { Color::Black };
+        let king_file = back_rank_king(board, color);
+        let rooks = back_rank_rooks(board, color);
+        let x_fen = chess960 || king_file.is_some_and(|king| king != 4);
+
+        let (side, rook_file) = match kastle_char.to_ascii_lowercase() {
+            'k' if x_fen => {
+                // without a rook on that side, this is the normal h file
+                let rook_file = king_file.and_then(|king| rooks.iter().copied().filter(|rook| *rook > king).max()).unwrap_or(7);
+                (CastlingSide::new(color, Imposter::King), Some(rook_file))
+            },
+            'q' if x_fen => {
+                let rook_file = king_file.and_then(|king| rooks.iter().copied().filter(|rook| *rook < king).min()).unwrap_or(0);
+                (CastlingSide::new(color, Imposter::Queen), Some(rook_file))
+            },
+            'k' => (CastlingSide::new(color, Imposter::King), (king_file.is_some() && rooks.contains(&7)).then_some(7)),
+            'q' => (CastlingSide::new(color, Imposter::Queen), (king_file.is_some() && rooks.contains(&0)).then_some(0)),
+            file_char @ 'a'..='h' => {
+                let rook_file = file_char as u8 - b'a';
+                let king = king_file.ok_or(FenError::InvalidCastling(info.to_string()))?;
+                if !rooks.contains(&rook_file) || rook_file == king {
+                    return Err(FenError::InvalidCastling(info.to_string()));
+                }
+                castling.chess960 = true;
+                let side = if rook_file > king { Imposter::King } else { Imposter::Queen };
+                (CastlingSide::new(color, side), Some(rook_file))
+            },
+            _ => return Err(FenError::InvalidCastling(info.to_string()))
+        };
+
+        if castling.can_castle(side) { // the same side twice
+            return Err(FenError::InvalidCastling(info.to_string()));
+        }
+        let Some(rook_file) = rook_file else { continue }; // a right that was lost, the rook is gone
+        castling.add_castle_right_with_rook(side, rook_file);
+        if x_fen {
+            castling.chess960 = true;
+        }
+    }
+    Ok(castling)
+}
+
+
+
+// handtere brett
+pub(crate) fn read_board(info: &str) -> Result<Bitboards, FenError> {
+    let mut board = Bitboards::new_empty();
+
+    let rows: Vec<&str> = info.split('/').collect();
+    if rows.len() != 8 {
+        return Err(FenError::WrongRankCount(info.to_string()));
+    }
+
+    for (row_nr, row) in rows.iter().enumerate() {
+        let mut col_nr: usize = 0;
+
+        for square_char in row.chars() {
+            if let Some(empty) = square_char.to_digit(10) {
+                if !(1..=8).contains(&empty) {
+                    return Err(FenError::InvalidRank(row.to_string()));
+                }
+                col_nr += empty as usize;
+            }
+            else {
+                let piece = PieceIndex::from_fen_char(square_char).ok_or(FenError::InvalidPiece(square_char))?;
+                if col_nr >= 8 {
+                    return Err(FenError::InvalidRank(row.to_string()));
+                }
+                let square = Square::from_coords(7-row_nr, col_nr).expect("read_board: row and column are checked to be on the board");
+                if piece.to_piece() == Piece::Pawn && (row_nr == 0 || row_nr == 7) {
+                    return Err(FenError::PawnOnBackRank(square));
+                }
+                board.set(piece, square);
+                col_nr += 1;
+            }
+        }
+
+        if col_nr != 8 { // too few or too many squares in the row
+            return Err(FenError::InvalidRank(row.to_string()));
+        }
+    }
+    Ok(board)
+}
+
+
+
+impl FromStr for Position {
+    type Err = FenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Position::try_from_fen(s)
+    }
+}
+
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FenField {
+    Board,
+    SideToMove,
+    Castling,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber,
+}
+
+// What went wrong when reading a fen string, the String/char is the part of the fen that was wrong
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(FenField),
+    TooManyFields(String),
+    WrongRankCount(String),     // the board field does not have 8 ranks
+    InvalidRank(String),        // a rank that does not have exactly 8 squares
+    InvalidPiece(char),
+    PawnOnBackRank(Square),     // pawns can't stand on the first or last rank
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl FenError {
+    // The fen field the error was found in
+    pub fn field(&self) -> Option<FenField> {
+        match self {
+            FenError::MissingField(field) => Some(*field),
+            FenError::TooManyFields(_) => None,
+            FenError::WrongRankCount(_) | FenError::InvalidRank(_) | FenError::InvalidPiece(_) | FenError::PawnOnBackRank(_) => Some(FenField::Board),
+            FenError::InvalidSideToMove(_) => Some(FenField::SideToMove),
+            FenError::InvalidCastling(_) => Some(FenField::Castling),
+            FenError::InvalidEnPassant(_) => Some(FenField::EnPassant),
+            FenError::InvalidHalfmoveClock(_) => Some(FenField::HalfmoveClock),
+            FenError::InvalidFullmoveNumber(_) => Some(FenField::FullmoveNumber),
+        }
+    }
+}
+
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FenField::Board => "board",
+            FenField::SideToMove => "side to move",
+            FenField::Castling => "castling rights",
+            FenField::EnPassant => "en passant square",
+            FenField::HalfmoveClock => "halfmove clock",
+            FenField::FullmoveNumber => "fullmove number",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "the fen is missing the {} field", field),
+            FenError::TooManyFields(token) => write!(f, "unexpected extra field in fen: '{}'", token),
+            FenError::WrongRankCount(token) => write!(f, "board must have 8 ranks separated by '/', got '{}'", token),
+            FenError::InvalidRank(token) => write!(f, "rank must have exactly 8 squares, got '{}'", token),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}' in board", c),
+            FenError::PawnOnBackRank(square) => write!(f, "there is a pawn on {}, pawns can't be on the first or last rank", square.square_str()),
+            FenError::InvalidSideToMove(token) => write!(f, "side to move must be 'w' or 'b', got '{}'", token),
+            FenError::InvalidCastling(token) => write!(f, "castling rights must be '-', some of 'KQkq' or rook files, got '{}'", token),
+            FenError::InvalidEnPassant(token) => write!(f, "en passant must be '-' or a square on rank 6 with white to move (rank 3 with black), got '{}'", token),
+            FenError::InvalidHalfmoveClock(token) => write!(f, "halfmove clock must be a number, got '{}'", token),
+            FenError::InvalidFullmoveNumber(token) => write!(f, "fullmove number must be a number, got '{}'", token),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+
+
+
+
+
 #[cfg(test)]
 mod test {
     use super::*;
     #[test]
     fn test_fen_read(){
-        let fen_string = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 1 0";
+        let fen_string = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
         let position = Position::read_fen(fen_string);
         dbg!(&position);
         
         dbg!(position.write_fen());
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_fen_round_trip(){
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                    "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w Kq c6 0 2",
+                    "4k3/8/8/8/8/8/8/4K3 b - - 37 112"] {
+            let position: Position = fen.parse().unwrap();
+            assert_eq!(position.write_fen(), fen);
+        }
+        // the clocks may be left out
+        assert_eq!(Position::try_from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap().write_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn test_fen_errors(){
+        let cases = [
+            ("", FenError::MissingField(FenField::Board)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", FenError::MissingField(FenField::SideToMove)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w", FenError::MissingField(FenField::Castling)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq", FenError::MissingField(FenField::EnPassant)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 x", FenError::TooManyFields("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1", FenError::WrongRankCount("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP".to_string())),
+            ("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRank("ppppppppp".to_string())),
+            ("rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRank("7".to_string())),
+            ("rnbqkbnr/pppppppp/45/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRank("45".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBXKBNR w KQkq - 0 1", FenError::InvalidPiece('X')),
+            ("P3k3/8/8/8/8/8/8/4K3 w - - 0 1", FenError::PawnOnBackRank(Square::A8)),
+            ("4k3/8/8/8/8/8/8/4K2p b - - 0 1", FenError::PawnOnBackRank(Square::H1)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::InvalidSideToMove("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1", FenError::InvalidCastling("KQkx".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KK - 0 1", FenError::InvalidCastling("KK".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1", FenError::InvalidEnPassant("e4".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1", FenError::InvalidEnPassant("z9".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 1", FenError::InvalidEnPassant("e3".to_string())),
+            ("rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR b KQkq e6 0 1", FenError::InvalidEnPassant("e6".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -3 1", FenError::InvalidHalfmoveClock("-3".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 one", FenError::InvalidFullmoveNumber("one".to_string())),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(Position::try_from_fen(fen), Err(error), "{}", fen);
+        }
+        assert_eq!(FenError::InvalidPiece('X').field(), Some(FenField::Board));
+        assert_eq!(FenError::InvalidCastling("KK".to_string()).to_string(), "castling rights must be '-', some of 'KQkq' or rook files, got 'KK'");
+    }
+
+    #[test]
+    fn test_castling_rights(){
+        // King on e1 and the rook on g1: K is a right that was lost, not chess960
+        let position = Position::try_from_fen("4k3/8/8/8/8/8/8/R3K1R1 w KQ - 0 1").unwrap();
+        assert!(!position.is_chess960());
+        assert!(!position.current.castling.can_castle(CastlingSide::WK));
+        assert!(position.current.castling.can_castle(CastlingSide::WQ));
+        assert_eq!(position.write_fen(), "4k3/8/8/8/8/8/8/R3K1R1 w Q - 0 1");
+
+        // The same read as chess960 is X-FEN, K is the g file rook
+        let position = Position::try_from_fen_960("4k3/8/8/8/8/8/8/R3K1R1 w KQ - 0 1").unwrap();
+        assert_eq!(position.current.castling.rook_file(CastlingSide::WK), 6);
+
+        // The king away from e, or a file letter, is chess960
+        let position = Position::try_from_fen("4k3/8/8/8/8/8/8/R4KR1 w KQ - 0 1").unwrap();
+        assert!(position.is_chess960());
+        assert_eq!(position.current.castling.rook_file(CastlingSide::WK), 6);
+        assert!(Position::try_from_fen("4k3/8/8/8/8/8/8/R3K1R1 w G - 0 1").unwrap().is_chess960());
+    }
+}
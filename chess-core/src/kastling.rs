@@ -1,5 +1,8 @@
 
 
+use crate::position::Color;
+use crate::square::Square;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CastlingSide{
     WK = 1, // Kvit Konge
@@ -8,6 +11,49 @@ pub enum CastlingSide{
     BQ = 1 << 3, // Svart Dronning
 }
 
+impl CastlingSide {
+    pub const ALL: [CastlingSide; 4] = [CastlingSide::WK, CastlingSide::WQ, CastlingSide::BK, CastlingSide::BQ];
+
+    pub fn new(color: Color, side: Imposter) -> Self {
+        match (color, side) {
+            (Color::White, Imposter::King) => CastlingSide::WK,
+            (Color::White, Imposter::Queen) => CastlingSide::WQ,
+            (Color::Black, Imposter::King) => CastlingSide::BK,
+            (Color::Black, Imposter::Queen) => CastlingSide::BQ,
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            CastlingSide::WK | CastlingSide::WQ => Color::White,
+            CastlingSide::BK | CastlingSide::BQ => Color::Black,
+        }
+    }
+
+    // 0 for white, 7 for black
+    pub fn back_rank(self) -> usize {
+        match self.color() {
+            Color::White => 0,
+            Color::Black => 7,
+        }
+    }
+
+    // Where the king and rook end up is the same in chess960 as in normal chess (g and f or c and d)
+    pub fn king_target(self) -> Square {
+        let col = match Imposter::from_castling_side(self) { Imposter::King => 6, Imposter::Queen => 2 };
+        Square::from_coords(self.back_rank(), col).expect("CastlingSide::king_target: the square is on the board")
+    }
+
+    pub fn rook_target(self) -> Square {
+        let col = match Imposter::from_castling_side(self) { Imposter::King => 5, Imposter::Queen => 3 };
+        Square::from_coords(self.back_rank(), col).expect("CastlingSide::rook_target: the square is on the board")
+    }
+
+    fn index(self) -> usize {
+        (self as u8).trailing_zeros() as usize
+    }
+}
+
 
 
 // side to move not included color
@@ -28,17 +74,36 @@ impl Imposter {
 }
 
 
+// The rook files are needed for chess960, where the rooks can start on any file (in normal chess they are a and h).
+// chess960 only changes how castling moves are written: the king "captures" its own rook (e1h1) instead of e1g1,
+// as both can mean the same move in chess960.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Castling{
     pub rights: u8,
+    pub rook_files: [u8; 4], // indexed like CastlingSide (WK, WQ, BK, BQ)
+    pub chess960: bool,
 }
  impl Castling{
     pub fn new()->Self{
-        Self { rights: 0 }
+        Self { rights: 0, rook_files: [7, 0, 7, 0], chess960: false }
     }
     pub fn add_castle_right(&mut self, castling_side: CastlingSide){
         self.rights |= castling_side as u8
     }
+    pub fn add_castle_right_with_rook(&mut self, castling_side: CastlingSide, rook_file: u8){
+        self.rights |= castling_side as u8;
+        self.rook_files[castling_side.index()] = rook_file;
+    }
+    pub fn rook_file(&self, castling_side: CastlingSide) -> u8 {
+        self.rook_files[castling_side.index()]
+    }
+    pub fn rook_square(&self, castling_side: CastlingSide) -> Square {
+        Square::from_coords(castling_side.back_rank(), self.rook_file(castling_side) as usize).expect("Castling::rook_square: rook files are always 0..8")
+    }
+    // The side that still can castle with the rook on that square (so the right is lost when the rook moves or is captured)
+    pub fn side_for_rook_square(&self, square: Square) -> Option<CastlingSide> {
+        CastlingSide::ALL.into_iter().find(|side| self.can_castle(*side) && self.rook_square(*side) == square)
+    }
     pub fn remove_castling_right(&mut self, castling_side: CastlingSide){
         self.rights &= !(castling_side as u8)
     }
@@ -84,4 +149,19 @@ mod test{
         assert!(castling.can_castle(CastlingSide::BQ));
         assert!(!castling.can_castle(CastlingSide::WK));
     }
+
+    #[test]
+    fn test_rook_files(){
+        let mut castling = Castling::new();
+        castling.add_castle_right(CastlingSide::WK);
+        castling.add_castle_right_with_rook(CastlingSide::BQ, 1);
+
+        assert_eq!(castling.rook_square(CastlingSide::WK), Square::H1);
+        assert_eq!(castling.rook_square(CastlingSide::BQ), Square::B8);
+        assert_eq!(castling.side_for_rook_square(Square::B8), Some(CastlingSide::BQ));
+        assert_eq!(castling.side_for_rook_square(Square::A8), None);
+        assert_eq!(castling.side_for_rook_square(Square::A1), None); // no right to castle there
+        assert_eq!(CastlingSide::BQ.king_target(), Square::C8);
+        assert_eq!(CastlingSide::WK.rook_target(), Square::F1);
+    }
 }
\ No newline at end of file
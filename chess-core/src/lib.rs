@@ -4,10 +4,29 @@ pub mod kastling;
 pub mod fen_string;
 pub mod square;
 pub mod attack;
+pub mod magic;
 pub mod piece;
 pub mod moves;
 pub mod bitboard_consts;
 pub mod movegen;
+pub mod zobrist;
+pub mod perft;
+pub mod outcome;
+pub mod san;
+pub mod uci;
+pub mod pgn;
+pub mod chess960;
+pub mod see;
+pub mod epd;
+pub mod book;
+pub mod tablebase;
+pub mod packed;
+pub mod builder;
+pub mod display;
+#[cfg(feature = "serde")]
+pub mod serialization;
+#[cfg(test)]
+mod test_util;
 
 #[cfg(test)]
 mod tests {
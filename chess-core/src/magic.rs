@@ -0,0 +1,272 @@
+use std::sync::LazyLock;
+
+use crate::attack::{bishop_attacks_rays, rook_attacks_rays};
+use crate::bitboard_consts::{FILE_A, FILE_H, RANK_1, RANK_8};
+use crate::board::Bitboard;
+use crate::square::Square;
+
+
+
+// Magic bitboards
+// For a slider on a square only the pieces on its rays matter (the "mask", without the edge squares as
+// nothing is behind them). Multiplying the masked occupancy with a magic number moves those bits into the
+// top of the u64, which is then used as an index into a table of precomputed attacks.
+// The magic numbers below were found with find_magic (trying random sparse numbers until one maps every
+// occupancy to a slot that has no other attack set in it). That search is too slow to do at every startup,
+// so only the attack tables are built at startup. Run the ignored test print_magics to find new ones.
+
+
+
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize, // where the attacks for this square start in MagicTables::attacks
+}
+
+impl Magic {
+    #[inline]
+    fn index(&self, all_occ: Bitboard) -> usize {
+        self.offset + ((all_occ.to_u64() & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct MagicTables {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<Bitboard>,
+}
+
+static TABLES: LazyLock<MagicTables> = LazyLock::new(MagicTables::generate);
+
+
+
+const ROOK_MAGICS: [u64; 64] = [
+    0xA080_0018_2040_0080, 0x0040_0020_0040_1000, 0x0180_3001_6000_8008, 0x0480_0408_0080_1001,
+    0x2A00_0810_8420_4200, 0x0480_0180_1200_3400, 0x0600_0100_8200_0428, 0x4200_0225_0C01_8042,
+    0x0040_8000_4000_2080, 0x0000_4000_2000_500C, 0x2002_0040_2200_1080, 0x0026_0022_0040_0810,
+    0x2000_8080_0800_0400, 0x0022_0002_0088_3104, 0x2C88_8080_0100_0200, 0x1112_0000_8042_0104,
+    0x0100_9080_0040_0020, 0x0080_8080_2000_4000, 0x0008_4100_1020_0300, 0x0014_8080_1000_0801,
+    0x0080_0500_1100_4800, 0x00D1_0100_0208_0400, 0x3221_5400_2108_0210, 0x1000_1200_0528_8244,
+    0x020C_4000_8024_8002, 0x4020_4112_0022_0082, 0x8028_1000_8020_0881, 0x1210_0011_0009_0020,
+    0x005A_0052_0008_4520, 0x0080_0400_8002_0080, 0x00D6_0022_0028_0401, 0x440B_210A_0000_6884,
+    0x0880_4010_2880_0080, 0x2000_8020_0880_4000, 0x2160_0010_4100_2900, 0x0800_0800_8080_1000,
+    0x0444_8204_0080_0800, 0x0000_0400_8080_0200, 0x0080_0281_0400_1028, 0x2808_1041_0200_0894,
+    0x0000_8001_0045_0024, 0x0000_4081_0202_0020, 0x2000_2001_0011_0044, 0x0110_0400_0800_4040,
+    0x0000_0800_0501_0010, 0x0002_0010_8812_0044, 0x0008_1002_0804_0001, 0x0001_0000_8045_002A,
+    0x0001_0020_4080_0100, 0x1602_2092_0049_0200, 0x1109_1000_2000_8880, 0x5000_1001_0020_0900,
+    0x0000_0400_8008_0080, 0x0003_0002_0400_0900, 0x4220_0806_3003_5400, 0x6140_8011_0000_6080,
+    0x1009_2341_0080_0039, 0x8000_2012_0080_4102, 0x5004_1008_2200_4082, 0x2802_0004_4010_0822,
+    0x0801_0084_0800_1017, 0x0002_0001_0804_1062, 0x8040_1211_0812_9044, 0x0400_0324_1100_8242,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x01A0_C202_0200_2A00, 0x2320_8101_0200_8401, 0x0408_8204_0221_8000, 0x1002_4081_010C_0040,
+    0x4104_0420_0104_1200, 0x8400_9024_2000_1100, 0x0011_0822_0220_001A, 0xAA80_2402_0804_0300,
+    0x21C8_0890_1408_0060, 0x0000_0202_1414_0090, 0x0280_040C_0C10_4000, 0x18B0_0220_8208_4040,
+    0x4004_0404_2081_0801, 0x4448_0088_0440_2804, 0x4081_0914_0104_4000, 0x2040_4C88_4802_1008,
+    0xC251_8005_1010_0100, 0x0620_2008_0280_8200, 0xA111_0002_0602_0200, 0x8001_0020_2040_8000,
+    0x0024_0110_84A0_0006, 0x2020_4002_0110_010A, 0x004A_0480_8804_2300, 0x0048_40A1_0420_8C20,
+    0x0010_C820_4448_1000, 0x0081_0412_0808_0820, 0x0040_2400_0800_4408, 0x2804_0100_0020_0880,
+    0x0504_0400_0041_0050, 0x100A_0080_1410_0090, 0x8212_0080_0748_0848, 0x0021_0200_0132_8424,
+    0x0001_9010_0008_2008, 0x0A01_0860_0003_1400, 0x0030_1402_0244_0800, 0x4084_8200_8018_0480,
+    0x0081_0104_00C2_0020, 0x8010_0100_4002_0042, 0x8024_1804_A036_0082, 0x044C_0092_0110_8440,
+    0xA104_0202_4130_1000, 0x0080_8C10_020B_0922, 0x0012_0422_0800_0100, 0x8000_0040_1202_1041,
+    0x8082_400B_0210_0B00, 0x0040_4088_0842_5680, 0x2062_1A04_4118_0400, 0x4022_2408_4880_8201,
+    0x0004_8401_2012_2000, 0x1000_4202_1042_0002, 0xC800_4040_4410_8100, 0x4009_800A_1044_0000,
+    0x011D_0105_1044_0840, 0x8000_8A20_4840_8024, 0x1062_0244_1808_8201, 0x3004_4108_0925_0010,
+    0x2820_8184_0911_4080, 0x0000_0424_0208_0404, 0x0200_0900_2084_1000, 0x0082_0900_0084_2408,
+    0x1010_0800_6002_4424, 0x1100_6004_8810_0100, 0x0022_0822_0468_1210, 0x0140_2880_9400_8024,
+];
+
+
+
+#[inline]
+pub fn rook_attacks(square: Square, all_occ: Bitboard) -> Bitboard {
+    let tables = &*TABLES;
+    tables.attacks[tables.rook[square.index() as usize].index(all_occ)]
+}
+
+#[inline]
+pub fn bishop_attacks(square: Square, all_occ: Bitboard) -> Bitboard {
+    let tables = &*TABLES;
+    tables.attacks[tables.bishop[square.index() as usize].index(all_occ)]
+}
+
+// Makes the tables ready now, instead of on the first lookup (nice to do before starting a clock)
+pub fn init() {
+    LazyLock::force(&TABLES);
+}
+
+
+
+// The squares where a blocker changes the attacks (the edges are only removed when the slider is not on them)
+fn rook_mask(square: Square) -> u64 {
+    let square_bb = square.to_bitboard();
+    let mut edges = Bitboard::new_empty();
+    if !square_bb.intersects(RANK_1) { edges |= RANK_1; }
+    if !square_bb.intersects(RANK_8) { edges |= RANK_8; }
+    if !square_bb.intersects(FILE_A) { edges |= FILE_A; }
+    if !square_bb.intersects(FILE_H) { edges |= FILE_H; }
+    (rook_attacks_rays(square, Bitboard::new_empty()) & !edges).to_u64()
+}
+
+fn bishop_mask(square: Square) -> u64 {
+    let edges = RANK_1 | RANK_8 | FILE_A | FILE_H;
+    (bishop_attacks_rays(square, Bitboard::new_empty()) & !edges).to_u64()
+}
+
+
+
+impl MagicTables {
+    fn generate() -> Self {
+        let mut attacks = Vec::new();
+        let mut rook = [Magic::default(); 64];
+        let mut bishop = [Magic::default(); 64];
+
+        for idx in 0..64 {
+            let square = Square::from_idx(idx).expect("MagicTables::generate: index outside the board");
+            rook[idx as usize] = fill_table(square, rook_mask(square), ROOK_MAGICS[idx as usize], rook_attacks_rays, &mut attacks);
+            bishop[idx as usize] = fill_table(square, bishop_mask(square), BISHOP_MAGICS[idx as usize], bishop_attacks_rays, &mut attacks);
+        }
+
+        MagicTables { rook, bishop, attacks }
+    }
+}
+
+// Every subset of the mask (carry rippler trick)
+fn subsets(mask: u64) -> Vec<u64> {
+    let mut result = Vec::with_capacity(1 << mask.count_ones());
+    let mut subset: u64 = 0;
+    loop {
+        result.push(subset);
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+    result
+}
+
+// Appends the attack table for the square to `attacks`
+fn fill_table(
+    square: Square,
+    mask: u64,
+    magic: u64,
+    slow_attacks: fn(Square, Bitboard) -> Bitboard,
+    attacks: &mut Vec<Bitboard>,
+    ) -> Magic {
+    let bits = mask.count_ones();
+    let offset = attacks.len();
+    let entry = Magic { mask, magic, shift: 64 - bits, offset };
+
+    attacks.resize(offset + (1 << bits), Bitboard::new_empty());
+    for occupancy in subsets(mask) {
+        let occupancy = Bitboard::from(occupancy);
+        attacks[entry.index(occupancy)] = slow_attacks(square, occupancy);
+    }
+    entry
+}
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    // splitmix64, seeded so the same magics are found every time
+    struct MagicRng(u64);
+
+    impl MagicRng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        }
+
+        // Magics with few bits set work much better
+        fn sparse(&mut self) -> u64 {
+            self.next() & self.next() & self.next()
+        }
+    }
+
+    // Finds a magic number for the square (slow, only used to make the tables in this file)
+    fn find_magic(
+        square: Square,
+        mask: u64,
+        slow_attacks: fn(Square, Bitboard) -> Bitboard,
+        rng: &mut MagicRng,
+        ) -> u64 {
+        let occupancies = subsets(mask);
+        let reference: Vec<Bitboard> = occupancies.iter().map(|occupancy| slow_attacks(square, Bitboard::from(*occupancy))).collect();
+        let bits = mask.count_ones();
+
+        // `used` remembers which try wrote a slot, so the table doesn't have to be cleared between tries
+        let mut table = vec![Bitboard::new_empty(); 1 << bits];
+        let mut used = vec![0u32; 1 << bits];
+        let mut attempt = 0u32;
+
+        loop {
+            let magic = rng.sparse();
+            if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                continue; // can't spread the bits out well enough
+            }
+            attempt += 1;
+
+            let candidate = Magic { mask, magic, shift: 64 - bits, offset: 0 };
+            let mut works = true;
+            for (occupancy, attack) in occupancies.iter().zip(reference.iter()) {
+                let index = candidate.index(Bitboard::from(*occupancy));
+                if used[index] != attempt {
+                    used[index] = attempt;
+                    table[index] = *attack;
+                } else if table[index] != *attack {
+                    works = false;
+                    break;
+                }
+            }
+
+            if works {
+                return magic;
+            }
+        }
+    }
+
+    #[test]
+    fn test_magic_matches_rays() {
+        let mut rng = rand::rng();
+        for idx in 0..64 {
+            let square = Square::from_idx(idx).unwrap();
+
+            // random boards with different densities
+            for _ in 0..2000 {
+                let occupancy = Bitboard::from((rng.random::<u64>() & rng.random::<u64>()) | (rng.random::<u64>() & rng.random::<u64>() & rng.random::<u64>()));
+                assert_eq!(rook_attacks(square, occupancy), rook_attacks_rays(square, occupancy), "rook on {:?}", square);
+                assert_eq!(bishop_attacks(square, occupancy), bishop_attacks_rays(square, occupancy), "bishop on {:?}", square);
+            }
+
+            assert_eq!(rook_attacks(square, Bitboard::new_empty()), rook_attacks_rays(square, Bitboard::new_empty()));
+            assert_eq!(bishop_attacks(square, Bitboard::from(u64::MAX)), bishop_attacks_rays(square, Bitboard::from(u64::MAX)));
+        }
+    }
+
+    // cargo test print_magics -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn print_magics() {
+        let mut rng = MagicRng(0x2545_F491_4F6C_DD1D);
+        let mut rook = Vec::new();
+        let mut bishop = Vec::new();
+        for idx in 0..64 {
+            let square = Square::from_idx(idx).unwrap();
+            rook.push(find_magic(square, rook_mask(square), rook_attacks_rays, &mut rng));
+            bishop.push(find_magic(square, bishop_mask(square), bishop_attacks_rays, &mut rng));
+        }
+        println!("ROOK_MAGICS: {:#018X?}", rook);
+        println!("BISHOP_MAGICS: {:#018X?}", bishop);
+    }
+}
@@ -2,12 +2,39 @@
 use crate::board::{Bitboards, Bitboard};
 use crate::moves::{BitMove, MoveList, MoveType};
 use crate::piece::{Piece, PieceIndex};
-use crate::square::{Square};
+use crate::square::{self, Square};
 use crate::kastling::{Castling, CastlingSide, Imposter};
 use crate::attack;
-use crate::bitboard_consts::{self, CORNERS};
+use crate::zobrist;
 use crate::position::{Color, Position};
 
+// What fill_legal_of_type generates. Captures and Quiets never overlap and together they are all the legal moves,
+// so a search can try the captures first and only generate the quiet moves if it needs them.
+// Queen promotions count as captures (quiescence search wants them) and the under promotions as quiet moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenType {
+    Captures, // captures (en passant too) and queen promotions, except capturing under promotions
+    Quiets,   // everything else: non captures, castling and under promotions
+    Evasions, // every legal move when in check, nothing when not in check
+    All,
+}
+
+impl GenType {
+    // Does a (legal) move belong to this kind, `in_check` is only used by Evasions
+    pub fn includes(self, mov: BitMove, in_check: bool) -> bool {
+        let is_capture_kind = match mov.get_premotion_piece() {
+            Some(piece) => piece == Piece::Queen,
+            None => mov.is_capture()
+        };
+        match self {
+            GenType::Captures => is_capture_kind,
+            GenType::Quiets => !is_capture_kind,
+            GenType::Evasions => in_check,
+            GenType::All => true,
+        }
+    }
+}
+
 impl Position{
     // Move generation (finds only the one for the color that currently is to move)
     // Finds all the pseudo legal (legal except for checks) moves in that position
@@ -33,18 +60,15 @@ impl Position{
             let start_square = Square::from_idx(idx).expect("Nr 1. Position::pseudo_legal finds an index outside of the square.");
             let (start_row, start_col) = start_square.to_coord();
 
-            // TODO This should probably be changed when implementing Mailbox (to only look for the color you are searching)
             let piece_index = self.current.bitboards.piece_on_square(start_square)
                                                           .expect("Position::pseudo_legal does not find a piece where it should be, as the index should be where the piece is.");
 
             let piece = Piece::from_piece_index(&piece_index);
 
-            let mut attacks = attack::get_attacks(piece_index, start_square, all_occ, color);
+            let mut attacks = attack::get_targets(piece_index, start_square, all_occ, color);
             attacks &= !my_occ;
 
 
-            let mut has_castled = false;
-
             'attack_loop: loop{
                 let attack_idx = match attacks.pop_lsb() {
                     Some(attack_index) => attack_index,
@@ -101,50 +125,76 @@ impl Position{
 
                 }
 
-                // Handle castling (it can castle if can_castle variable is set for that side and pieces are cleared)
-                if piece == Piece::King && !has_castled {
-                    has_castled = true;
-                    const BB_MASKS: [(CastlingSide, Square, Bitboard); 4] = [
-                        (CastlingSide::WK, Square::G1, Bitboard::new_const(0x60)), // The bitboards represent the squares between king and rook
-                        (CastlingSide::WQ, Square::C1, Bitboard::new_const(0x0E)),
-                        (CastlingSide::BK, Square::G8, Bitboard::new_const(0x6000000000000000)),
-                        (CastlingSide::BQ, Square::C8, Bitboard::new_const(0x0E00000000000000)),
-                    ];
-
-                    for (side, target_square, mask) in BB_MASKS.iter().cloned() {
-                        let is_right_color = match (color, side) {
-                            (Color::White, CastlingSide::WK | CastlingSide::WQ) => true,
-                            (Color::Black, CastlingSide::BK | CastlingSide::BQ) => true,
-                            _ => false,
-                        };
-
-                        if is_right_color
-                            && self.current.castling.can_castle(side)
-                            && !all_occ.intersects(mask) // makes shure no piece is between rook and king
-                        {
-                            move_list.add(BitMove::new(
-                                start_square,
-                                target_square,
-                                is_capture,
-                                MoveType::Castling(Imposter::from_castling_side(side)),
-                            ));
-                        }
-                    }
+                move_list.add(BitMove::new(start_square, end_square, is_capture, move_type));
                 }
 
+            if piece == Piece::King{
+                self.castling_moves(start_square, move_list);
+            }
+            }
+        }
+
+    // Handle castling (it can castle if can_castle variable is set for that side, every square the king and the rook
+    // walk over is empty except for the two of them, and the king does not start in, pass through or land on an attacked square).
+    // This works for chess960 as well, where the king and rook can start anywhere on the back rank.
+    fn castling_moves(&self, king_square: Square, move_list: &mut MoveList){
+        let all_occ = self.current.bitboards.all_occupancy;
+        let color = self.current.side_to_move;
+        let castling = self.current.castling;
+
+        if self.is_square_attacked(king_square, !color){ // can't castle out of check
+            return;
+        }
+
+        for side in [Imposter::King, Imposter::Queen] {
+            let castling_side = CastlingSide::new(color, side);
+            if !castling.can_castle(castling_side) {
+                continue;
+            }
 
+            let rook_square = castling.rook_square(castling_side);
+            let king_target = castling_side.king_target();
+            let rook_target = castling_side.rook_target();
 
-                move_list.add(BitMove::new(start_square, end_square, is_capture, move_type));
+            // The king and the rook don't block themself (or each other)
+            let others = all_occ & !king_square.to_bitboard() & !rook_square.to_bitboard();
+            let must_be_empty = square::between(king_square, king_target) | king_target.to_bitboard()
+                              | square::between(rook_square, rook_target) | rook_target.to_bitboard();
+            if others.intersects(must_be_empty) {
+                continue;
+            }
+
+            // The rook is removed too, so a rook standing between an enemy rook and the king's path can't hide the attack
+            let opponent_occ = self.current.bitboards.occupancy(!color);
+            let king_path = square::between(king_square, king_target) | king_target.to_bitboard();
+            let mut path = king_path;
+            let mut path_attacked = false;
+            while let Some(idx) = path.pop_lsb() {
+                let square = Square::from_idx(idx).expect("castling_moves: path squares are on the board");
+                if self.attackers_to(square, others).intersects(opponent_occ) {
+                    path_attacked = true;
+                    break;
                 }
             }
+            if path_attacked {
+                continue;
+            }
+
+            // Chess960 castling is written as the king taking its own rook
+            let end_square = if castling.chess960 { rook_square } else { king_target };
+            move_list.add(BitMove::new(king_square, end_square, false, MoveType::Castling(side)));
         }
+    }
     
 
     // Changes the position according to the move  // TODO find a beter way, i just did what my first instingt was
-    pub fn make_move(&mut self, mov: BitMove){// TODO Mailbox must be updated here when implemented
+    pub fn make_move(&mut self, mov: BitMove){
 
         self.history.push(self.current);
 
+        // the castling rights and en passant square are xored back in at the end, after they have been uppdated
+        self.current.zobrist_key ^= zobrist::castling(self.current.castling.rights) ^ self.current.en_passant_key();
+
         // predefined variables
         let color = self.current.side_to_move;
         
@@ -176,7 +226,7 @@ impl Position{
         }
         
 
-        self.current.bitboards.remove(piece_index, start_square);
+        self.current.remove_piece(piece_index, start_square);
 
 
         self.current.halfmove_clock += 1; // this is always incremented unles a pawn move or a capture is made
@@ -194,18 +244,20 @@ impl Position{
                     Color::Black => (3, PieceIndex::WhitePawn),
                 };
                 let captured_square = Square::from_coords(enemy_pawn_rank, end_square.to_coord().1).expect("Make_move: didnt find a piece on square that is suposed to be enemy piece captured, during en-passant");
-                self.current.bitboards.remove(captured_piece, captured_square);
+                self.current.remove_piece(captured_piece, captured_square);
             }
             else{
                 let captured_piece = self.current.bitboards.piece_on_square(end_square).expect("Make_move: didnt find a piece on square that is suposed to be enemy piece captured");
-                self.current.bitboards.remove(captured_piece, end_square);
+                self.current.remove_piece(captured_piece, end_square);
             }
         }
 
         // Setting the end square (both pawn premotion and normal)
+        // For castling the end square is the rook in chess960, so the king and rook are placed by the castling code below
         match mov.get_premotion_piece(){ // This must be after capture, otherwise we might screw with the bitboards (set a bit before removing others)
-            Some(promo_piece) => self.current.bitboards.set(PieceIndex::from_piece(promo_piece, color), end_square),
-            None => self.current.bitboards.set(piece_index, end_square)
+            Some(promo_piece) => self.current.set_piece(PieceIndex::from_piece(promo_piece, color), end_square),
+            None if mov.get_castle_side().is_some() => (),
+            None => self.current.set_piece(piece_index, end_square)
         }
 
         self.current.en_passant = None;
@@ -222,66 +274,24 @@ impl Position{
 
         
 
-        // ej etter ej skreiv dinna koda:
-        //  (×_×)
-        //   /|\
-        //   / \
-        // Den må forenklast og forbedrast
-        match mov.get_castle_side(){
-            Some(side) => {
-                
-                let (rock_piece, rock_row, start_rock_col, end_rook_col) = match color {
-                    Color::Black => {
-                        if side == Imposter::King{
-                            self.current.castling.remove_castling_right(CastlingSide::BK);
-                            (PieceIndex::BlackRook, 7, 7, 5)
-                        }
-                        else {
-                            self.current.castling.remove_castling_right(CastlingSide::BQ);
-                            (PieceIndex::BlackRook, 7, 0, 3)
-                        }
-                    },
-                    Color::White => {
-                        if side == Imposter::King{
-                            self.current.castling.remove_castling_right(CastlingSide::WK);
-                            (PieceIndex::WhiteRook, 0, 7, 5)
-                        }
-                        else {
-                            self.current.castling.remove_castling_right(CastlingSide::WQ);
-                            (PieceIndex::WhiteRook, 0, 0, 3)
-                        }
-                    }
-                };
+        // The rook is taken off before the king is put down, in chess960 the king can end up where the rook was
+        if let Some(side) = mov.get_castle_side() {
+            let castling_side = CastlingSide::new(color, side);
+            let rook_piece = PieceIndex::from_piece(Piece::Rook, color);
 
-                let rook_square_start = Square::from_coords(rock_row, start_rock_col).expect("make_move: Invalid rook square during castling");
-                self.current.bitboards.remove(rock_piece, rook_square_start);
-                let rook_square_end = Square::from_coords(rock_row, end_rook_col).expect("make_move: Invalid rook square during castling");
-                self.current.bitboards.set(rock_piece, rook_square_end);
-            },
-            None => ()
+            self.current.remove_piece(rook_piece, self.current.castling.rook_square(castling_side));
+            self.current.set_piece(piece_index, castling_side.king_target());
+            self.current.set_piece(rook_piece, castling_side.rook_target());
         }
 
-        fn castling_side_for_corner(sq: Square) -> Option<CastlingSide> {
-                match sq {
-                    Square::A1 => Some(CastlingSide::WQ),
-                    Square::H1 => Some(CastlingSide::WK),
-                    Square::A8 => Some(CastlingSide::BQ),
-                    Square::H8 => Some(CastlingSide::BK),
-                    _       => None,
-                }
-            }
-
-        if let Some(cap_piece) = captured_piece { // See if anything captures the rock in the corner
-            if cap_piece.to_piece() == Piece::Rook && end_square.to_bitboard().intersects(bitboard_consts::CORNERS){
-                if let Some(side) = castling_side_for_corner(end_square) {
-                    self.current.castling.remove_castling_right(side);
-                }
-            }
+        // The castling right is lost when the rook moves or is captured
+        if captured_piece.is_some_and(|cap_piece| cap_piece.to_piece() == Piece::Rook)
+            && let Some(side) = self.current.castling.side_for_rook_square(end_square) {
+            self.current.castling.remove_castling_right(side);
         }
-        if piece == Piece::Rook && start_square.to_bitboard().intersects(CORNERS){ // See if rook moves
-            if let Some(side) = castling_side_for_corner(start_square) {
-                self.current.castling.remove_castling_right(side);
-            }
+        if piece == Piece::Rook
+            && let Some(side) = self.current.castling.side_for_rook_square(start_square) {
+            self.current.castling.remove_castling_right(side);
         }
     
         if piece == Piece::King{
@@ -296,47 +306,200 @@ impl Position{
 
 
         self.current.side_to_move = !self.current.side_to_move;
+        self.current.zobrist_key ^= zobrist::side_to_move()
+                                  ^ zobrist::castling(self.current.castling.rights)
+                                  ^ self.current.en_passant_key();
 
-        
+        debug_assert_eq!(self.current.zobrist_key, self.current.compute_key(), "make_move: incremental zobrist key differs from the recomputed one");
     }
 
-    // TODO I should swap this out for faster check for wether it is ilegal or not
-    // checks if the move generates a check and thus is legal or not
+    // Checks if the move leaves the own king in check (by making it on a copy, so this is slow, fill_legal does not use it)
     pub fn makes_self_check(&self, mov: BitMove) -> bool{
         let mut temp_pos = self.clone();
         temp_pos.make_move(mov);
 
-        let mut move_list = MoveList::new_empty();
+        let color = !temp_pos.current.side_to_move;
+        match temp_pos.king_square(color) {
+            Some(king_square) => temp_pos.is_square_attacked(king_square, !color),
+            None => false
+        }
+    }
 
-        let self_king = match !temp_pos.current.side_to_move {
-            Color::Black => PieceIndex::BlackKing,
-            Color::White => PieceIndex::WhiteKing
+    // Only the legal moves, without making any of them:
+    // - the king can go to squares that are not attacked (looked at with the king removed, so it can't step back along a checking ray)
+    // - in double check only the king can move
+    // - in single check the other pieces must capture the checker or block (the check mask)
+    // - a pinned piece can only move along the line through it and the king
+    // - en passant removes two pieces from the same rank, so it is tested on its own (it can uncover a check along the rank)
+    pub fn fill_legal(&self, move_list: &mut MoveList){
+        self.fill_legal_of_type(GenType::All, move_list);
+    }
+
+    // Only the legal moves of one kind, see GenType
+    pub fn fill_legal_of_type(&self, gen_type: GenType, move_list: &mut MoveList){
+        move_list.clear();
+
+        let color = self.current.side_to_move;
+        let boards = &self.current.bitboards;
+        let king_square = match self.king_square(color) {
+            Some(square) => square,
+            None => { // no king to keep out of check, so everything is legal
+                let mut list = MoveList::new_empty();
+                self.pseudo_legal(&mut list);
+                for mov in list.iter().filter(|mov| gen_type.includes(**mov, false)) {
+                    move_list.add(*mov);
+                }
+                return;
+            }
         };
 
-        temp_pos.pseudo_legal(&mut move_list);
-        for moves in move_list.iter(){
-            if temp_pos.current.bitboards.piece_on_square(moves.get_end_square()) == Some(self_king){
-                return true
+        let us = boards.occupancy(color);
+        let them = boards.occupancy(!color);
+        let all_occ = boards.all_occupancy;
+
+        let checkers = self.attackers_to(king_square, all_occ) & them;
+        if gen_type == GenType::Evasions && checkers.is_empty() {
+            return;
+        }
+
+        // The squares pieces (not pawns, their promotions are sorted by piece) may move to
+        let gen_targets = match gen_type {
+            GenType::Captures => them,
+            GenType::Quiets => !all_occ,
+            GenType::Evasions | GenType::All => !us,
+        };
+
+        // King moves
+        let without_king = all_occ & !king_square.to_bitboard();
+        let mut king_targets = attack::king_attacks(king_square) & gen_targets;
+        while let Some(idx) = king_targets.pop_lsb() {
+            let end_square = Square::from_idx(idx).expect("fill_legal: king target is on the board");
+            if !self.attackers_to(end_square, without_king).intersects(them) {
+                move_list.add(BitMove::new(king_square, end_square, them.is_occupied(end_square), MoveType::Quiet));
+            }
+        }
+
+        if checkers.count() > 1 {
+            return;
+        }
+
+        let mut first_checker = checkers;
+        let check_mask = match first_checker.pop_lsb() {
+            Some(idx) => {
+                let checker = Square::from_idx(idx).expect("fill_legal: checker is on the board");
+                square::between(king_square, checker) | checkers
+            },
+            None => {
+                if gen_type != GenType::Captures {
+                    self.castling_moves(king_square, move_list);
+                }
+                !Bitboard::new_empty()
+            }
+        };
+
+        let pinned = self.pinned(color);
+
+        // Every other piece
+        let mut pieces = us & !king_square.to_bitboard();
+        while let Some(idx) = pieces.pop_lsb() {
+            let start_square = Square::from_idx(idx).expect("fill_legal: piece is on the board");
+            let piece_index = boards.piece_on_square(start_square).expect("fill_legal: the occupancy says there is a piece here");
+
+            let mut allowed = check_mask & !us;
+            if pinned.is_occupied(start_square) {
+                allowed &= square::line(king_square, start_square);
+            }
+
+            if piece_index.to_piece() == Piece::Pawn {
+                self.legal_pawn_moves(gen_type, start_square, allowed, king_square, checkers, move_list);
+                continue;
+            }
+
+            let mut targets = attack::get_attacks(piece_index, start_square, all_occ, color) & allowed & gen_targets;
+            while let Some(target_idx) = targets.pop_lsb() {
+                let end_square = Square::from_idx(target_idx).expect("fill_legal: target is on the board");
+                move_list.add(BitMove::new(start_square, end_square, them.is_occupied(end_square), MoveType::Quiet));
             }
         }
-        false
     }
 
-    // TODO This should probably change to a faster way, but for now i am to lacy
-    pub fn fill_legal(&self, move_list: &mut MoveList){
-        move_list.clear();
+    // Pushes, double pushes, captures and promotions that land on an allowed square, and en passant
+    fn legal_pawn_moves(&self, gen_type: GenType, start_square: Square, allowed: Bitboard, king_square: Square, checkers: Bitboard, move_list: &mut MoveList){
+        let color = self.current.side_to_move;
+        let boards = &self.current.bitboards;
+        let them = boards.occupancy(!color);
+        let all_occ = boards.all_occupancy;
+        let (row, col) = start_square.to_coord();
+        let (forward, start_row): (isize, usize) = match color {
+            Color::White => (1, 1),
+            Color::Black => (-1, 6),
+        };
 
-        let mut list = MoveList::new_empty();
-        self.pseudo_legal(&mut list);
+        let in_check = !checkers.is_empty();
+        let add = |move_list: &mut MoveList, end_square: Square, is_capture: bool, move_type: MoveType| {
+            let end_row = end_square.to_coord().0;
+            if end_row == 0 || end_row == 7 { // Premotion
+                for promo_piece in [Piece::Bishop, Piece::Knight, Piece::Rook, Piece::Queen] {
+                    let mov = BitMove::new(start_square, end_square, is_capture, MoveType::Promotion(promo_piece));
+                    if gen_type.includes(mov, in_check) {
+                        move_list.add(mov);
+                    }
+                }
+            } else {
+                let mov = BitMove::new(start_square, end_square, is_capture, move_type);
+                if gen_type.includes(mov, in_check) {
+                    move_list.add(mov);
+                }
+            }
+        };
 
-        for mov in list.iter(){
-            if !self.makes_self_check(*mov){
-                move_list.add(*mov);
+        // Pushes. A pawn on the last rank has no moves at all
+        let Some(one_step) = Square::from_coords((row as isize + forward) as usize, col) else {
+            return;
+        };
+        if !all_occ.is_occupied(one_step) {
+            if allowed.is_occupied(one_step) {
+                add(move_list, one_step, false, MoveType::Quiet);
+            }
+            if row == start_row {
+                let two_steps = Square::from_coords((row as isize + 2 * forward) as usize, col).expect("legal_pawn_moves: double push is on the board");
+                if !all_occ.is_occupied(two_steps) && allowed.is_occupied(two_steps) {
+                    add(move_list, two_steps, false, MoveType::EnPassant); // the double push flag
+                }
+            }
+        }
+
+        // Captures
+        let mut captures = attack::pawn_attacks(start_square, color) & them & allowed;
+        while let Some(idx) = captures.pop_lsb() {
+            add(move_list, Square::from_idx(idx).expect("legal_pawn_moves: capture is on the board"), true, MoveType::Quiet);
+        }
+
+        // En passant: make it on the occupancy and see if any slider then sees the king
+        if gen_type == GenType::Quiets {
+            return;
+        }
+        if let Some(en_passant_square) = self.current.en_passant {
+            if !attack::pawn_attacks(start_square, color).is_occupied(en_passant_square) {
+                return;
+            }
+            let captured_square = Square::from_coords(row, en_passant_square.to_coord().1).expect("legal_pawn_moves: captured pawn is on the board");
+            let occupancy = (all_occ & !start_square.to_bitboard() & !captured_square.to_bitboard()) | en_passant_square.to_bitboard();
+
+            let their_straight = boards.pieces(Piece::Rook, !color) | boards.pieces(Piece::Queen, !color);
+            let their_diagonal = boards.pieces(Piece::Bishop, !color) | boards.pieces(Piece::Queen, !color);
+            let their_leapers = boards.pieces(Piece::Knight, !color) | boards.pieces(Piece::Pawn, !color);
+
+            let slider_check = attack::rook_attacks(king_square, occupancy).intersects(their_straight)
+                            || attack::bishop_attacks(king_square, occupancy).intersects(their_diagonal);
+            let other_check = !(checkers & their_leapers & !captured_square.to_bitboard()).is_empty();
+            if !slider_check && !other_check {
+                move_list.add(BitMove::new(start_square, en_passant_square, true, MoveType::EnPassant));
             }
         }
     }
 
-    // !!! Only for debuging
+    // Every legal move for the side to move
     pub fn legal_moves(&self)->MoveList{
         let mut move_list = MoveList::new_empty();
         self.fill_legal(&mut move_list);
@@ -357,6 +520,7 @@ mod test{
     use std::ptr::dangling;
 
     use crate::moves::Move;
+    use crate::test_util::{random_walk, test_rng};
     use rand::Rng;
     
 
@@ -369,6 +533,9 @@ mod test{
         let mut rng = rand::rng();
         for i in 0..50{
             let moves = position.legal_moves();
+            if moves.size() == 0{ // mate or stalemate
+                break;
+            }
             let nr = rng.random_range(0..moves.size());
             position.make_move(*moves.get(nr).unwrap());
             dbg!(position.current.bitboards.all_occupancy);
@@ -382,4 +549,140 @@ mod test{
 
 
     }
+
+    fn castling_targets(fen: &str) -> Vec<Square>{
+        let position = Position::new(Some(fen));
+        position.legal_moves().iter()
+            .filter(|mov| mov.get_castle_side().is_some())
+            .map(|mov| mov.get_end_square())
+            .collect()
+    }
+
+    #[test]
+    fn test_castling_legality(){
+        // Nothing in the way
+        assert_eq!(castling_targets("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), vec![Square::G1, Square::C1]);
+        // In check
+        assert!(castling_targets("r3k2r/8/8/8/8/8/4r3/R3K2R w KQkq - 0 1").is_empty());
+        // f1 attacked, queen side still fine
+        assert_eq!(castling_targets("r3k2r/8/8/8/8/8/5r2/R3K2R w KQkq - 0 1"), vec![Square::C1]);
+        // g8 attacked
+        assert_eq!(castling_targets("r3k2r/8/8/8/8/8/8/R3K1R1 b Qkq - 0 1"), vec![Square::C8]);
+        // b1 attacked only matters for the rook, so queen side castling is still allowed
+        assert_eq!(castling_targets("r3k2r/8/8/8/8/8/1r6/R3K3 w Qkq - 0 1"), vec![Square::C1]);
+    }
+
+    // The legal moves the slow way: every pseudo legal move that does not leave the king in check
+    fn filtered_pseudo_legal(position: &Position) -> Vec<String> {
+        let mut list = MoveList::new_empty();
+        position.pseudo_legal(&mut list);
+        let mut moves: Vec<String> = list.iter().filter(|mov| !position.makes_self_check(**mov)).map(|mov| format!("{:?}", mov)).collect();
+        moves.sort();
+        moves
+    }
+
+    fn legal_sorted(position: &Position) -> Vec<String> {
+        let mut moves: Vec<String> = position.legal_moves().iter().map(|mov| format!("{:?}", mov)).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn test_legal_matches_filtered_pseudo_legal(){
+        let mut rng = test_rng();
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"] {
+            for _ in 0..20{
+                let mut position = Position::new(Some(fen));
+                random_walk(&mut position, 60, &mut rng, |position| {
+                    assert_eq!(legal_sorted(position), filtered_pseudo_legal(position), "{}", position.write_fen());
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_types_add_up(){
+        let mut rng = test_rng();
+        for fen in ["r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"] {
+            for _ in 0..20{
+                let mut position = Position::new(Some(fen));
+                random_walk(&mut position, 60, &mut rng, |position| {
+                    let all = position.legal_moves();
+                    let mut captures = MoveList::new_empty();
+                    position.fill_legal_of_type(GenType::Captures, &mut captures);
+                    let mut quiets = MoveList::new_empty();
+                    position.fill_legal_of_type(GenType::Quiets, &mut quiets);
+                    let mut evasions = MoveList::new_empty();
+                    position.fill_legal_of_type(GenType::Evasions, &mut evasions);
+
+                    // Every move is in exactly one of captures and quiets
+                    assert_eq!(captures.size() + quiets.size(), all.size(), "{}", position.write_fen());
+                    for mov in all.iter() {
+                        assert!(captures.iter().any(|other| other == mov) != quiets.iter().any(|other| other == mov), "{}", position.write_fen());
+                    }
+                    assert!(captures.iter().all(|mov| GenType::Captures.includes(*mov, false)));
+                    assert!(quiets.iter().all(|mov| GenType::Quiets.includes(*mov, false)));
+
+                    if position.is_in_check() {
+                        assert_eq!(evasions.size(), all.size());
+                    } else {
+                        assert_eq!(evasions.size(), 0);
+                    }
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_type_promotions(){
+        // b7 can push to b8 or take on a8
+        let position = Position::new(Some("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1"));
+        let mut captures = MoveList::new_empty();
+        position.fill_legal_of_type(GenType::Captures, &mut captures);
+        assert_eq!(captures.size(), 2); // bxa8=Q and b8=Q
+        assert!(captures.iter().all(|mov| mov.get_premotion_piece() == Some(Piece::Queen)));
+
+        let mut quiets = MoveList::new_empty();
+        position.fill_legal_of_type(GenType::Quiets, &mut quiets);
+        assert_eq!(quiets.iter().filter(|mov| mov.get_premotion_piece().is_some()).count(), 6);
+    }
+
+    #[test]
+    fn test_legal_special_cases(){
+        // En passant would uncover the rook on h5
+        let position = Position::new(Some("8/8/8/KPp4r/8/8/8/7k w - c6 0 1"));
+        assert!(position.legal_moves().iter().all(|mov| !mov.is_en_passant()));
+        // ... but not if the capture takes the pawn that gives check
+        let position = Position::new(Some("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1"));
+        assert!(position.legal_moves().iter().any(|mov| mov.is_en_passant()));
+        // Pinned en passant along the diagonal
+        let position = Position::new(Some("k7/8/8/8/3Pp3/8/8/4K2B b - d3 0 1"));
+        assert!(position.legal_moves().iter().all(|mov| !mov.is_en_passant()));
+
+        // The king can't step back along the ray of the checking rook
+        let position = Position::new(Some("4k3/8/8/8/8/8/8/r3K3 w - - 0 1"));
+        let king_moves: Vec<Square> = position.legal_moves().iter().map(|mov| mov.get_end_square()).collect();
+        assert!(!king_moves.contains(&Square::F1));
+        assert_eq!(king_moves.len(), 3); // d2, e2, f2
+
+        // Double check: only the king moves
+        let position = Position::new(Some("4k3/8/8/8/1b6/8/3N4/r3K3 w - - 0 1"));
+        assert!(position.legal_moves().iter().all(|mov| mov.get_start_square() == Square::E1));
+
+        // A pinned rook can move along the pin, but not off it
+        let position = Position::new(Some("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1"));
+        assert!(position.legal_moves().iter().filter(|mov| mov.get_start_square() == Square::E2).all(|mov| mov.get_end_square().to_coord().1 == 4));
+        assert_eq!(position.legal_moves().iter().filter(|mov| mov.get_start_square() == Square::E2).count(), 5);
+
+        // A pawn on the last rank has no moves (P3k3/8/8/8/8/8/8/4K3 w - - 0 1, which the fen reader turns down)
+        let mut position = Position::new(Some("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        position.current.bitboards.set(PieceIndex::WhitePawn, Square::A8);
+        assert_eq!(position.legal_moves().size(), 5);
+    }
 }
\ No newline at end of file
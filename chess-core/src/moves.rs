@@ -28,6 +28,14 @@ impl BitMove{
         BitMove::encode(Move{from, to, is_capture, move_type})
     }
 
+    // The 16 bits as they are, for storing moves compactly
+    pub fn to_bits(self) -> u16{
+        self.0
+    }
+    pub fn from_bits(bits: u16) -> Self{
+        BitMove(bits)
+    }
+
     pub fn get_start_square(&self)->Square{
         Square::from_idx((self.0 >> FROM_SHIFT) as u8).expect("get_start_square (BitMove) finds an un squarable index from:")
     }
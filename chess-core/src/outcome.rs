@@ -0,0 +1,200 @@
+use crate::bitboard_consts::{DARK_SQUARES, LIGHT_SQUARES};
+use crate::moves::MoveList;
+use crate::piece::{Piece, PieceIndex};
+use crate::position::{Color, Position};
+
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Checkmate { winner: Color },
+    Draw(DrawReason),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrawReason {
+    Stalemate,
+    InsufficientMaterial,
+    FivefoldRepetition,
+    SeventyFiveMoveRule,
+    ThreefoldRepetition, // can be claimed by a player
+    FiftyMoveRule,       // can be claimed by a player
+}
+
+impl Outcome {
+    pub fn winner(&self) -> Option<Color> {
+        match self {
+            Outcome::Checkmate { winner } => Some(*winner),
+            Outcome::Draw(_) => None
+        }
+    }
+
+    pub fn is_draw(&self) -> bool {
+        matches!(self, Outcome::Draw(_))
+    }
+}
+
+impl DrawReason {
+    // The threefold repetition and fifty move draws only happen if a player claims them, the rest end the game right away
+    pub fn is_automatic(&self) -> bool {
+        !matches!(self, DrawReason::ThreefoldRepetition | DrawReason::FiftyMoveRule)
+    }
+}
+
+
+
+impl Position {
+    // Is the game over, and how. Draws that have to be claimed (threefold, fifty moves) are reported too,
+    // check DrawReason::is_automatic if the difference matters.
+    pub fn outcome(&self) -> Option<Outcome> {
+        let mut move_list = MoveList::new_empty();
+        self.fill_legal(&mut move_list);
+
+        // Mate comes first, also when it is made on the move that would have been a fifty move draw
+        if move_list.size() == 0 {
+            if self.is_in_check() {
+                return Some(Outcome::Checkmate { winner: !self.current.side_to_move });
+            }
+            return Some(Outcome::Draw(DrawReason::Stalemate));
+        }
+
+        if self.is_insufficient_material() {
+            return Some(Outcome::Draw(DrawReason::InsufficientMaterial));
+        }
+
+        let repetitions = self.repetition_count();
+        if repetitions >= 5 {
+            return Some(Outcome::Draw(DrawReason::FivefoldRepetition));
+        }
+        if self.current.halfmove_clock >= 150 {
+            return Some(Outcome::Draw(DrawReason::SeventyFiveMoveRule));
+        }
+        if repetitions >= 3 {
+            return Some(Outcome::Draw(DrawReason::ThreefoldRepetition));
+        }
+        if self.current.halfmove_clock >= 100 {
+            return Some(Outcome::Draw(DrawReason::FiftyMoveRule));
+        }
+        None
+    }
+
+    // How many times the current position has been on the board (counting the current one).
+    // Only looks back to the last capture or pawn move, as nothing before that can be the same position.
+    pub fn repetition_count(&self) -> usize {
+        let key = self.current.key();
+        let reversible_plies = self.current.halfmove_clock as usize;
+
+        // Same side to move only, so every second snapshot
+        1 + self.history.iter().rev()
+                .take(reversible_plies)
+                .skip(1)
+                .step_by(2)
+                .filter(|snapshot| snapshot.key() == key)
+                .count()
+    }
+
+    // Neither side can ever mate: only kings and minor pieces, and either at most one minor piece
+    // or only bishops that all stand on the same square color
+    pub fn is_insufficient_material(&self) -> bool {
+        let boards = &self.current.bitboards;
+        let heavy_or_pawn = [Piece::Pawn, Piece::Rook, Piece::Queen].iter()
+            .any(|piece| boards.pieces(*piece, Color::White).to_u64() != 0 || boards.pieces(*piece, Color::Black).to_u64() != 0);
+        if heavy_or_pawn {
+            return false;
+        }
+
+        let knights = boards.get_bitboard(PieceIndex::WhiteKnight) | boards.get_bitboard(PieceIndex::BlackKnight);
+        let bishops = boards.get_bitboard(PieceIndex::WhiteBishop) | boards.get_bitboard(PieceIndex::BlackBishop);
+        let minor_count = (knights | bishops).to_u64().count_ones();
+
+        if minor_count <= 1 {
+            return true;
+        }
+        knights.to_u64() == 0 && ((bishops & LIGHT_SQUARES).to_u64() == 0 || (bishops & DARK_SQUARES).to_u64() == 0)
+    }
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::square::Square;
+    use crate::test_util::play;
+
+    #[test]
+    fn test_checkmate_and_stalemate() {
+        let mut position = Position::new(None); // fool's mate
+        play(&mut position, &[(Square::F2, Square::F3), (Square::E7, Square::E5), (Square::G2, Square::G4), (Square::D8, Square::H4)]);
+        assert_eq!(position.outcome(), Some(Outcome::Checkmate { winner: Color::Black }));
+        assert_eq!(position.outcome().unwrap().winner(), Some(Color::Black));
+
+        let stalemate = Position::new(Some("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"));
+        assert_eq!(stalemate.outcome(), Some(Outcome::Draw(DrawReason::Stalemate)));
+
+        assert_eq!(Position::new(None).outcome(), None);
+    }
+
+    #[test]
+    fn test_move_rules() {
+        let position = Position::new(Some("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80"));
+        assert_eq!(position.outcome(), None);
+        let position = Position::new(Some("4k3/8/8/8/8/8/4P3/R3K3 w - - 100 80"));
+        assert_eq!(position.outcome(), Some(Outcome::Draw(DrawReason::FiftyMoveRule)));
+        assert!(!DrawReason::FiftyMoveRule.is_automatic());
+        let position = Position::new(Some("4k3/8/8/8/8/8/4P3/R3K3 w - - 150 80"));
+        assert_eq!(position.outcome(), Some(Outcome::Draw(DrawReason::SeventyFiveMoveRule)));
+
+        // mate wins even on the 100th half move
+        let position = Position::new(Some("R3k3/8/4K3/8/8/8/8/8 b - - 100 80"));
+        assert_eq!(position.outcome(), Some(Outcome::Checkmate { winner: Color::White }));
+    }
+
+    #[test]
+    fn test_repetition() {
+        let knight_dance = [(Square::G1, Square::F3), (Square::G8, Square::F6), (Square::F3, Square::G1), (Square::F6, Square::G8)];
+        let mut position = Position::new(None);
+        assert_eq!(position.repetition_count(), 1);
+
+        play(&mut position, &knight_dance);
+        assert_eq!(position.repetition_count(), 2);
+        assert_eq!(position.outcome(), None);
+
+        play(&mut position, &knight_dance);
+        assert_eq!(position.repetition_count(), 3);
+        assert_eq!(position.outcome(), Some(Outcome::Draw(DrawReason::ThreefoldRepetition)));
+
+        play(&mut position, &knight_dance);
+        play(&mut position, &knight_dance);
+        assert_eq!(position.outcome(), Some(Outcome::Draw(DrawReason::FivefoldRepetition)));
+
+        // pawn moves make the old positions impossible to reach again
+        play(&mut position, &[(Square::E2, Square::E4), (Square::E7, Square::E5)]);
+        play(&mut position, &knight_dance);
+        assert_eq!(position.repetition_count(), 2);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let dead = ["4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                    "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+                    "4kb2/8/8/8/8/8/8/4K3 w - - 0 1",
+                    "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",   // both bishops on dark squares
+                    "4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1"];  // same colored bishops
+        let alive = ["4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+                     "4k3/8/8/8/8/8/8/3NKB2 w - - 0 1",
+                     "4kb2/8/8/8/8/8/8/3BK3 w - - 0 1",   // bishops on different colors
+                     "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+                     "4k3/8/8/8/8/8/8/R3K3 w - - 0 1"];
+        for fen in dead {
+            assert!(Position::new(Some(fen)).is_insufficient_material(), "{}", fen);
+            assert_eq!(Position::new(Some(fen)).outcome(), Some(Outcome::Draw(DrawReason::InsufficientMaterial)), "{}", fen);
+        }
+        for fen in alive {
+            assert!(!Position::new(Some(fen)).is_insufficient_material(), "{}", fen);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+
+use crate::board::{Bitboard, Bitboards};
+use crate::builder::check_pieces;
+use crate::kastling::{Castling, CastlingSide};
+use crate::pgn::GameResult;
+use crate::piece::PieceIndex;
+use crate::position::{Color, Position, Snapshot};
+use crate::square::Square;
+
+
+
+// A fixed size binary encoding of a position (32 bytes), for storing millions of positions as training data.
+//   0..8    occupancy (u64), a bit for every square with a piece on it
+//   8..24   the piece on every occupied square, one nibble each (PieceIndex), in square order, low nibble first
+//   24      bit 0: black to move, bit 1: chess960, bit 2: en passant, bits 3..6: the en passant file
+//   25      castling rights
+//   26..28  the rook file of every castling right, 3 bits each, in CastlingSide order
+//   28..30  halfmove clock (u16)
+//   30..32  fullmove number (u16)
+// Numbers are little endian. A position has at most 32 pieces, which is what there is room for.
+// The history is not stored, and the zobrist key is computed again when decoding.
+//
+// Training data files are "CCPK", version and then records of a packed position, a score (i16, centipawns for the
+// side to move) and the game result (u8), read and written one record at a time by PackedReader and PackedWriter.
+
+
+
+pub const PACKED_SIZE: usize = 32;
+pub const RECORD_SIZE: usize = PACKED_SIZE + 3;
+pub const MAX_PACKED_PIECES: u32 = 32;
+
+const MAGIC: &[u8; 4] = b"CCPK";
+const VERSION: u8 = 1;
+
+pub type PackedPosition = [u8; PACKED_SIZE];
+
+
+
+impl Snapshot {
+    pub fn encode(&self) -> Result<PackedPosition, PackError> {
+        let occupancy = self.bitboards.all_occupancy;
+        if occupancy.count() > MAX_PACKED_PIECES {
+            return Err(PackError::TooManyPieces(occupancy.count()));
+        }
+
+        let mut bytes = [0; PACKED_SIZE];
+        bytes[0..8].copy_from_slice(&occupancy.to_u64().to_le_bytes());
+        for (nr, square) in occupancy.iter().enumerate() {
+            let piece = self.bitboards.piece_on_square(square).expect("Snapshot::encode: occupancy and mailbox agree") as u8;
+            bytes[8 + nr / 2] |= piece << (4 * (nr % 2));
+        }
+
+        let mut flags = 0;
+        if self.side_to_move == Color::Black {
+            flags |= 1;
+        }
+        if self.castling.chess960 {
+            flags |= 2;
+        }
+        if let Some(square) = self.en_passant {
+            flags |= 4 | (square.to_coord().1 as u8) << 3;
+        }
+        bytes[24] = flags;
+        bytes[25] = self.castling.rights;
+
+        let rook_files = CastlingSide::ALL.iter().enumerate()
+            .fold(0u16, |files, (nr, side)| files | (self.castling.rook_file(*side) as u16 & 0b111) << (3 * nr));
+        bytes[26..28].copy_from_slice(&rook_files.to_le_bytes());
+        bytes[28..30].copy_from_slice(&self.halfmove_clock.to_le_bytes());
+        bytes[30..32].copy_from_slice(&self.fullmove_number.to_le_bytes());
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &PackedPosition) -> Result<Snapshot, PackError> {
+        let invalid = |reason: &str| PackError::InvalidPosition(reason.to_string());
+
+        let occupancy = Bitboard::new_const(u64::from_le_bytes(bytes[0..8].try_into().expect("Snapshot::decode: 8 bytes")));
+        if occupancy.count() > MAX_PACKED_PIECES {
+            return Err(PackError::TooManyPieces(occupancy.count()));
+        }
+        let mut bitboards = Bitboards::new_empty();
+        for (nr, square) in occupancy.iter().enumerate() {
+            let nibble = (bytes[8 + nr / 2] >> (4 * (nr % 2))) & 0xF;
+            if nibble >= 12 {
+                return Err(invalid("unknown piece"));
+            }
+            bitboards.set(PieceIndex::try_from(nibble as usize).expect("Snapshot::decode: checked above"), square);
+        }
+        check_pieces(&bitboards).map_err(|error| PackError::InvalidPosition(error.to_string()))?;
+
+        let flags = bytes[24];
+        if flags >> 6 != 0 || bytes[25] > 0b1111 {
+            return Err(invalid("unknown flags"));
+        }
+        let side_to_move = if flags & 1 == 0 { Color::White } else { Color::Black };
+        // The en passant square is behind the pawn that just moved two squares
+        let en_passant = if flags & 4 != 0 {
+            let row = if side_to_move == Color::White { 5 } else { 2 };
+            Square::from_coords(row, (flags >> 3 & 0b111) as usize)
+        } else {
+            None
+        };
+
+        let rook_files = u16::from_le_bytes([bytes[26], bytes[27]]);
+        let mut castling = Castling::new();
+        castling.rights = bytes[25];
+        castling.chess960 = flags & 2 != 0;
+        for nr in 0..4 {
+            castling.rook_files[nr] = (rook_files >> (3 * nr) & 0b111) as u8;
+        }
+
+        let mut snapshot = Snapshot {
+            bitboards,
+            side_to_move,
+            castling,
+            en_passant,
+            halfmove_clock: u16::from_le_bytes([bytes[28], bytes[29]]),
+            fullmove_number: u16::from_le_bytes([bytes[30], bytes[31]]),
+            zobrist_key: 0,
+        };
+        snapshot.zobrist_key = snapshot.compute_key();
+        Ok(snapshot)
+    }
+}
+
+impl Position {
+    pub fn encode(&self) -> Result<PackedPosition, PackError> {
+        self.current.encode()
+    }
+
+    pub fn decode(bytes: &PackedPosition) -> Result<Position, PackError> {
+        Ok(Position { current: Snapshot::decode(bytes)?, history: vec![] })
+    }
+}
+
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainingEntry {
+    pub position: Position,
+    pub score: i16, // centipawns, for the side to move
+    pub result: GameResult,
+}
+
+impl TrainingEntry {
+    pub fn to_bytes(&self) -> Result<[u8; RECORD_SIZE], PackError> {
+        encode_record(&self.position, self.score, self.result)
+    }
+
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Result<Self, PackError> {
+        let position = Position::decode(bytes[0..PACKED_SIZE].try_into().expect("TrainingEntry::from_bytes: 32 bytes"))?;
+        let score = i16::from_le_bytes([bytes[PACKED_SIZE], bytes[PACKED_SIZE + 1]]);
+        let result = match bytes[PACKED_SIZE + 2] {
+            0 => GameResult::Unknown,
+            1 => GameResult::WhiteWins,
+            2 => GameResult::BlackWins,
+            3 => GameResult::Draw,
+            _ => return Err(PackError::InvalidPosition("unknown game result".to_string())),
+        };
+        Ok(TrainingEntry { position, score, result })
+    }
+}
+
+
+
+fn encode_record(position: &Position, score: i16, result: GameResult) -> Result<[u8; RECORD_SIZE], PackError> {
+    let mut bytes = [0; RECORD_SIZE];
+    bytes[0..PACKED_SIZE].copy_from_slice(&position.encode()?);
+    bytes[PACKED_SIZE..PACKED_SIZE + 2].copy_from_slice(&score.to_le_bytes());
+    bytes[PACKED_SIZE + 2] = match result {
+        GameResult::Unknown => 0,
+        GameResult::WhiteWins => 1,
+        GameResult::BlackWins => 2,
+        GameResult::Draw => 3,
+    };
+    Ok(bytes)
+}
+
+
+
+// Writes the header at once and then one record for every position
+pub struct PackedWriter<W: Write> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: Write> PackedWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, PackError> {
+        writer.write_all(MAGIC).and_then(|_| writer.write_all(&[VERSION])).map_err(|error| PackError::Io(error.to_string()))?;
+        Ok(PackedWriter { writer, written: 0 })
+    }
+
+    pub fn write(&mut self, position: &Position, score: i16, result: GameResult) -> Result<(), PackError> {
+        let bytes = encode_record(position, score, result)?;
+        self.writer.write_all(&bytes).map_err(|error| PackError::Io(error.to_string()))?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn write_entry(&mut self, entry: &TrainingEntry) -> Result<(), PackError> {
+        self.write(&entry.position, entry.score, entry.result)
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn into_inner(mut self) -> Result<W, PackError> {
+        self.writer.flush().map_err(|error| PackError::Io(error.to_string()))?;
+        Ok(self.writer)
+    }
+}
+
+// Reads the header at once and then one record at a time: for entry in PackedReader::new(BufReader::new(file))? { ... }
+pub struct PackedReader<R: Read> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> PackedReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, PackError> {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header).map_err(|error| match error.kind() {
+            ErrorKind::UnexpectedEof => PackError::InvalidFile("not a packed position file".to_string()),
+            _ => PackError::Io(error.to_string()),
+        })?;
+        if &header[0..4] != MAGIC {
+            return Err(PackError::InvalidFile("not a packed position file".to_string()));
+        }
+        if header[4] != VERSION {
+            return Err(PackError::InvalidFile("unknown version".to_string()));
+        }
+        Ok(PackedReader { reader, done: false })
+    }
+
+    // None at the end of the file
+    pub fn read_entry(&mut self) -> Result<Option<TrainingEntry>, PackError> {
+        let mut bytes = [0; RECORD_SIZE];
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            match self.reader.read(&mut bytes[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {},
+                Err(error) => return Err(PackError::Io(error.to_string())),
+            }
+        }
+        match filled {
+            0 => Ok(None),
+            RECORD_SIZE => TrainingEntry::from_bytes(&bytes).map(Some),
+            _ => Err(PackError::TruncatedRecord(filled)),
+        }
+    }
+}
+
+impl<R: Read> Iterator for PackedReader<R> {
+    type Item = Result<TrainingEntry, PackError>;
+
+    // Stops after an io error or a cut off record, a record with a broken position is skipped past
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.read_entry();
+        if matches!(entry, Ok(None) | Err(PackError::Io(_) | PackError::TruncatedRecord(_))) {
+            self.done = true;
+        }
+        entry.transpose()
+    }
+}
+
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackError {
+    Io(String),
+    TooManyPieces(u32),
+    InvalidPosition(String),
+    InvalidFile(String),
+    TruncatedRecord(usize), // the number of bytes there were
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::Io(message) => write!(f, "could not read or write packed positions: {}", message),
+            PackError::TooManyPieces(count) => write!(f, "a packed position has room for {} pieces, not {}", MAX_PACKED_PIECES, count),
+            PackError::InvalidPosition(reason) => write!(f, "invalid packed position: {}", reason),
+            PackError::InvalidFile(reason) => write!(f, "invalid packed position file: {}", reason),
+            PackError::TruncatedRecord(len) => write!(f, "the last record is {} bytes, not {}", len, RECORD_SIZE),
+        }
+    }
+}
+
+impl std::error::Error for PackError {}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{random_walk, test_rng};
+    use rand::Rng;
+
+    const FENS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 2",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 37 300",
+        "4k3/8/8/8/8/8/8/4K3 b - - 65535 65535",
+    ];
+
+    #[test]
+    fn test_round_trip() {
+        for fen in FENS {
+            let position = Position::new(Some(fen));
+            let decoded = Position::decode(&position.encode().unwrap()).unwrap();
+            assert_eq!(decoded.write_fen(), fen);
+            assert_eq!(decoded.current, position.current);
+        }
+
+        // Chess960 keeps its rook files
+        for id in [0, 518, 959] {
+            let position = Position::chess960(id).unwrap();
+            let decoded = Position::decode(&position.encode().unwrap()).unwrap();
+            assert!(decoded.is_chess960());
+            assert_eq!(decoded.write_shredder_fen(), position.write_shredder_fen());
+            assert_eq!(decoded.current, position.current);
+        }
+
+        // Positions from random games
+        let mut rng = test_rng();
+        for _ in 0..20 {
+            let mut position = Position::new(None);
+            let plies = rng.random_range(0..150);
+            random_walk(&mut position, plies, &mut rng, |position| {
+                let decoded = Position::decode(&position.encode().unwrap()).unwrap();
+                assert_eq!(decoded.write_fen(), position.write_fen());
+                assert_eq!(decoded.current.key(), position.current.key());
+            });
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let crowded = Position::new(Some("rnbqkbnr/pppppppp/8/8/8/7P/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+        assert_eq!(crowded.encode(), Err(PackError::TooManyPieces(33)));
+
+        let mut bytes = Position::new(None).encode().unwrap();
+        bytes[8] |= 0xF;
+        assert!(matches!(Position::decode(&bytes), Err(PackError::InvalidPosition(_))));
+        let mut bytes = Position::new(None).encode().unwrap();
+        bytes[24] |= 0x80;
+        assert!(matches!(Position::decode(&bytes), Err(PackError::InvalidPosition(_))));
+
+        // The rook on a1 made a pawn, and the black king made a second white one
+        let mut bytes = Position::new(Some("4k3/8/8/8/8/8/8/R3K3 w - - 0 1")).encode().unwrap();
+        bytes[8] &= 0xF0;
+        assert_eq!(Position::decode(&bytes), Err(PackError::InvalidPosition("there is a pawn on a1, pawns can't be on the first or last rank".to_string())));
+        let mut bytes = Position::new(Some("4k3/8/8/8/8/8/8/4K3 w - - 0 1")).encode().unwrap();
+        bytes[8] = PieceIndex::WhiteKing as u8 | (PieceIndex::WhiteKing as u8) << 4;
+        assert!(matches!(Position::decode(&bytes), Err(PackError::InvalidPosition(_))));
+    }
+
+    #[test]
+    fn test_stream() {
+        let entries: Vec<TrainingEntry> = FENS.iter().zip([GameResult::WhiteWins, GameResult::Draw, GameResult::BlackWins, GameResult::Unknown].iter().cycle())
+            .enumerate()
+            .map(|(nr, (fen, result))| TrainingEntry { position: Position::new(Some(fen)), score: nr as i16 * 150 - 400, result: *result })
+            .collect();
+
+        let mut writer = PackedWriter::new(Vec::new()).unwrap();
+        for entry in &entries {
+            writer.write_entry(entry).unwrap();
+        }
+        assert_eq!(writer.written(), entries.len());
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(bytes.len(), 5 + RECORD_SIZE * entries.len());
+
+        let read: Vec<TrainingEntry> = PackedReader::new(bytes.as_slice()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(read, entries);
+        for (entry, fen) in read.iter().zip(FENS) {
+            assert_eq!(entry.position.write_fen(), fen);
+            assert_eq!(TrainingEntry::from_bytes(&entry.to_bytes().unwrap()).unwrap(), *entry);
+        }
+
+        // A cut off file gives the whole records and then an error
+        let read: Vec<_> = PackedReader::new(&bytes[..bytes.len() - 10]).unwrap().collect();
+        assert_eq!(read.len(), entries.len());
+        assert_eq!(read.last().unwrap().as_ref().unwrap_err(), &PackError::TruncatedRecord(RECORD_SIZE - 10));
+
+        assert!(matches!(PackedReader::new(&b"CCTB\x01"[..]), Err(PackError::InvalidFile(_))));
+        assert!(matches!(PackedReader::new(&b"CC"[..]), Err(PackError::InvalidFile(_))));
+        assert_eq!(PackedReader::new(&bytes[..5]).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+
+use crate::moves::{BitMove, MoveList};
+use crate::position::Position;
+
+
+
+// Perft counts every leaf node of the legal move tree to a given depth. Comparing the numbers against the
+// published ones (or against another engine with divide) is the standard way to find move generator bugs.
+
+
+
+impl Position {
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let mut move_list = MoveList::new_empty();
+        self.fill_legal(&mut move_list);
+
+        if depth == 1 { // no need to make the moves, they are all leaves
+            return move_list.size() as u64;
+        }
+
+        let mut nodes = 0;
+        for mov in move_list.iter() {
+            self.make_move(*mov);
+            nodes += self.perft(depth - 1);
+            self.unmake_move().expect("perft: unmake_move after make_move should always work");
+        }
+        nodes
+    }
+
+    // Perft split on the first move, so a wrong count can be followed down to the move that causes it
+    pub fn perft_divide(&mut self, depth: u32) -> Divide {
+        let mut move_list = MoveList::new_empty();
+        self.fill_legal(&mut move_list);
+
+        let mut moves = Vec::with_capacity(move_list.size());
+        if depth == 0 {
+            return Divide { moves };
+        }
+
+        for mov in move_list.iter() {
+            self.make_move(*mov);
+            moves.push((*mov, self.perft(depth - 1)));
+            self.unmake_move().expect("perft_divide: unmake_move after make_move should always work");
+        }
+        Divide { moves }
+    }
+}
+
+
+
+// The result of perft_divide, Display prints it the way Stockfish and most other engines do:
+// e2e4: 20
+// ...
+//
+// Nodes searched: 400
+pub struct Divide {
+    pub moves: Vec<(BitMove, u64)>,
+}
+
+impl Divide {
+    pub fn total(&self) -> u64 {
+        self.moves.iter().map(|(_, nodes)| nodes).sum()
+    }
+}
+
+impl fmt::Display for Divide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (mov, nodes) in &self.moves {
+            writeln!(f, "{}: {}", mov.to_uci(), nodes)?;
+        }
+        writeln!(f)?;
+        write!(f, "Nodes searched: {}", self.total())
+    }
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // https://www.chessprogramming.org/Perft_Results
+    const PERFT_SUITE: [(&str, &str, &[u64]); 6] = [
+        ("start", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281, 4865609]),
+        ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862, 4085603]),
+        ("position 3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238, 674624]),
+        ("position 4", "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467, 422333]),
+        ("position 5", "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379, 2103487]),
+        ("position 6", "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890, 3894594]),
+    ];
+
+    // Debug builds are a lot slower, so only go deep in release (cargo test --release)
+    const MAX_NODES: u64 = if cfg!(debug_assertions) { 200_000 } else { 5_000_000 };
+
+    #[test]
+    fn test_perft_suite() {
+        for (name, fen, counts) in PERFT_SUITE {
+            let mut position = Position::new(Some(fen));
+            for (depth, expected) in counts.iter().enumerate() {
+                if *expected > MAX_NODES {
+                    break;
+                }
+                assert_eq!(position.perft(depth as u32 + 1), *expected, "{} at depth {}\n{}", name, depth + 1, position.perft_divide(depth as u32 + 1));
+            }
+            assert_eq!(position, Position::new(Some(fen))); // everything was unmade again
+        }
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let mut position = Position::new(None);
+        let divide = position.perft_divide(2);
+        assert_eq!(divide.moves.len(), 20);
+        assert_eq!(divide.total(), 400);
+
+        let output = divide.to_string();
+        assert!(output.lines().any(|line| line == "e2e4: 20"));
+        assert!(output.lines().any(|line| line == "g1f3: 20"));
+        assert!(output.ends_with("\n\nNodes searched: 400"));
+    }
+}
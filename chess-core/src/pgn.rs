@@ -0,0 +1,836 @@
+use std::fmt;
+use std::io::BufRead;
+
+use crate::fen_string::FenError;
+use crate::moves::BitMove;
+use crate::position::{Color, Position};
+use crate::san::SanError;
+
+
+
+// PGN (Portable Game Notation) reading and writing.
+// PgnReader reads one game at a time from anything that is BufRead, so big files never have to be in memory.
+// Every move is checked by replaying it with parse_san and make_move, and errors say where in the file they are.
+
+
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const MAX_LINE_LENGTH: usize = 80;
+
+// The tags every PGN game should have, in the order they must be written
+pub const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    #[default]
+    Unknown, // still going, abandoned or not known
+}
+
+impl GameResult {
+    pub fn from_pgn(token: &str) -> Option<Self> {
+        match token {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None
+        }
+    }
+
+    pub fn to_pgn(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+}
+
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PgnMove {
+    pub mov: BitMove,
+    pub nags: Vec<u8>,                  // $1, $2 ... ("!" and "?" are read as the nags they stand for)
+    pub starting_comment: Option<String>, // a comment before the move (only before the first move of a line)
+    pub comment: Option<String>,        // the comment after the move
+    pub variations: Vec<Vec<PgnMove>>,  // other moves that could have been played instead of this one
+}
+
+impl PgnMove {
+    pub fn new(mov: BitMove) -> Self {
+        PgnMove { mov, ..Default::default() }
+    }
+}
+
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Game {
+    pub tags: Vec<(String, String)>, // in the order they were read
+    pub moves: Vec<PgnMove>,         // the main line
+    pub result: GameResult,
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game::default()
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    // Changes the tag if it is there, adds it at the end if not
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    // The FEN tag if there is one, the normal start position if not (as chess960 if the Variant tag says so)
+    pub fn start_position(&self) -> Result<Position, FenError> {
+        let fen = self.tag("FEN").unwrap_or(START_FEN);
+        match self.tag("Variant").map(|variant| variant.to_ascii_lowercase()) {
+            Some(variant) if variant.contains("960") || variant.contains("fischer") => Position::try_from_fen_960(fen),
+            _ => Position::try_from_fen(fen)
+        }
+    }
+
+    // The position after the last move of the main line
+    pub fn end_position(&self) -> Result<Position, FenError> {
+        let mut position = self.start_position()?;
+        for pgn_move in &self.moves {
+            position.make_move(pgn_move.mov);
+        }
+        Ok(position)
+    }
+
+    // Fails if the FEN tag is broken or a move is not legal where it is played (the moves are not checked when they are added)
+    pub fn to_pgn(&self) -> Result<String, PgnError> {
+        let mut pgn = String::new();
+
+        // The seven tag roster first, then the rest
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.to_pgn(),
+                _ => self.tag(name).unwrap_or(default)
+            };
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+        }
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.iter().any(|(roster_name, _)| roster_name == name) {
+                pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+            }
+        }
+        pgn.push('\n');
+
+        let position = self.start_position().map_err(|error| PgnError::writing(PgnErrorKind::InvalidFen(error)))?;
+        let mut words = Vec::new();
+        write_line(&mut words, &position, &self.moves)?;
+        words.push(self.result.to_pgn().to_string());
+
+        for line in wrap_words(&words) {
+            pgn.push_str(&line);
+            pgn.push('\n');
+        }
+        Ok(pgn)
+    }
+}
+
+
+
+// Writing
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn push_comment(words: &mut Vec<String>, comment: &str) {
+    let mut comment_words: Vec<String> = comment.split_whitespace().map(String::from).collect();
+    if comment_words.is_empty() {
+        words.push("{}".to_string());
+        return;
+    }
+    comment_words[0].insert(0, '{');
+    comment_words.last_mut().expect("push_comment: checked that it is not empty").push('}');
+    words.extend(comment_words);
+}
+
+// Turns the moves into words ("1.", "e4", "{a", "comment}", "(", ...), numbering them from the position
+fn write_line(words: &mut Vec<String>, position: &Position, moves: &[PgnMove]) -> Result<(), PgnError> {
+    let mut position = position.clone();
+    let mut needs_number = true; // black moves only get a number at the start or after a comment or variation
+
+    for pgn_move in moves {
+        if let Some(comment) = &pgn_move.starting_comment {
+            push_comment(words, comment);
+        }
+
+        // to_san and make_move trust that the move is legal
+        if position.find_move(pgn_move.mov) != Some(pgn_move.mov) {
+            return Err(PgnError::writing(PgnErrorKind::NotLegal(pgn_move.mov.to_uci())));
+        }
+
+        // The number goes in the same word as the move so a line never ends with "12."
+        let move_number = position.current.fullmove_number;
+        let san = position.to_san(pgn_move.mov);
+        words.push(match position.current.side_to_move {
+            Color::White => format!("{}. {}", move_number, san),
+            Color::Black if needs_number => format!("{}... {}", move_number, san),
+            Color::Black => san
+        });
+        needs_number = false;
+
+        for nag in &pgn_move.nags {
+            words.push(format!("${}", nag));
+        }
+        if let Some(comment) = &pgn_move.comment {
+            push_comment(words, comment);
+            needs_number = true;
+        }
+        for variation in &pgn_move.variations {
+            words.push("(".to_string());
+            write_line(words, &position, variation)?;
+            words.push(")".to_string());
+            needs_number = true;
+        }
+
+        position.make_move(pgn_move.mov);
+    }
+    Ok(())
+}
+
+// Greedy line wrapping, no space after "(" or before ")"
+fn wrap_words(words: &[String]) -> Vec<String> {
+    let mut glued: Vec<String> = Vec::new();
+    let mut open_paren = false;
+    for word in words {
+        if word == ")" {
+            match glued.last_mut() {
+                Some(last) => last.push(')'),
+                None => glued.push(")".to_string())
+            }
+        } else if word == "(" {
+            open_paren = true;
+        } else if open_paren {
+            glued.push(format!("({}", word));
+            open_paren = false;
+        } else {
+            glued.push(word.clone());
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in glued {
+        if !line.is_empty() && line.len() + 1 + word.len() > MAX_LINE_LENGTH {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+
+
+// Errors
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnErrorKind {
+    Io(String),
+    UnexpectedChar(char),
+    UnterminatedString,
+    UnterminatedComment,
+    InvalidNag(String),
+    UnexpectedToken(String),
+    InvalidFen(FenError),
+    IllegalMove { san: String, error: SanError },
+    UnclosedVariation,
+    MissingResult, // the game ended (end of file or a new tag section) without a result
+    NotLegal(String), // a move (as uci) that can't be played where it is in a game being written
+}
+
+// line and column start at 1, they are 0 for errors when writing
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: PgnErrorKind,
+}
+
+impl PgnError {
+    fn writing(kind: PgnErrorKind) -> Self {
+        PgnError { line: 0, column: 0, kind }
+    }
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {}, column {}: ", self.line, self.column)?;
+        }
+        match &self.kind {
+            PgnErrorKind::Io(message) => write!(f, "could not read: {}", message),
+            PgnErrorKind::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            PgnErrorKind::UnterminatedString => write!(f, "tag value is missing the closing '\"'"),
+            PgnErrorKind::UnterminatedComment => write!(f, "comment is missing the closing '}}'"),
+            PgnErrorKind::InvalidNag(nag) => write!(f, "invalid annotation '{}'", nag),
+            PgnErrorKind::UnexpectedToken(token) => write!(f, "unexpected '{}'", token),
+            PgnErrorKind::InvalidFen(error) => write!(f, "invalid FEN tag: {}", error),
+            PgnErrorKind::IllegalMove { san, error } => write!(f, "move '{}': {}", san, error),
+            PgnErrorKind::UnclosedVariation => write!(f, "variation is missing the closing ')'"),
+            PgnErrorKind::MissingResult => write!(f, "game has no result (1-0, 0-1, 1/2-1/2 or *)"),
+            PgnErrorKind::NotLegal(uci) => write!(f, "move '{}' is not legal in the position it is played from", uci),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+
+
+// Reading
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    TagOpen,
+    TagClose,
+    Str(String),
+    Symbol(String), // tag names and moves
+    Comment(String),
+    VariationOpen,
+    VariationClose,
+    Nag(u8),
+    MoveNumber,
+    Result(GameResult),
+}
+
+#[derive(Clone, Debug)]
+struct Spanned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+// Gives out the file one char at a time, reading a new line when the old one is used up
+struct Lexer<R: BufRead> {
+    reader: R,
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl<R: BufRead> Lexer<R> {
+    fn error(&self, kind: PgnErrorKind) -> PgnError {
+        PgnError { line: self.line, column: self.pos + 1, kind }
+    }
+
+    // Reads lines until there is something left in the current one (false at the end of the file)
+    fn fill(&mut self) -> Result<bool, PgnError> {
+        while self.pos >= self.chars.len() {
+            let mut buffer = String::new();
+            let read = self.reader.read_line(&mut buffer).map_err(|error| self.error(PgnErrorKind::Io(error.to_string())))?;
+            if read == 0 {
+                return Ok(false);
+            }
+            self.line += 1;
+            self.pos = 0;
+            self.chars = buffer.chars().collect();
+            if self.chars.first() == Some(&'%') { // escape line, ignored
+                self.chars.clear();
+            }
+        }
+        Ok(true)
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, PgnError> {
+        Ok(if self.fill()? { Some(self.chars[self.pos]) } else { None })
+    }
+
+    fn bump(&mut self) -> Result<Option<char>, PgnError> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    // Throws away the rest of the line
+    fn skip_line(&mut self) {
+        self.pos = self.chars.len();
+    }
+
+    // After an error: skips the rest of the game's tags and its moves, to the next line that starts with '['
+    // after a line that does not (where the next game should start)
+    fn skip_to_next_game(&mut self) -> Result<(), PgnError> {
+        let mut in_tags = self.chars.first() == Some(&'[');
+        self.skip_line();
+        while self.fill()? {
+            let tag_line = self.chars.first() == Some(&'[');
+            if tag_line && !in_tags {
+                return Ok(());
+            }
+            in_tags = tag_line;
+            self.skip_line();
+        }
+        Ok(())
+    }
+
+    fn next_token(&mut self) -> Result<Option<Spanned>, PgnError> {
+        // whitespace
+        while let Some(c) = self.peek()? {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        let (line, column) = (self.line, self.pos + 1);
+        let c = match self.bump()? {
+            Some(c) => c,
+            None => return Ok(None)
+        };
+
+        let token = match c {
+            '[' => Token::TagOpen,
+            ']' => Token::TagClose,
+            '(' => Token::VariationOpen,
+            ')' => Token::VariationClose,
+            '*' => Token::Result(GameResult::Unknown),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match self.chars.get(self.pos).copied() { // strings can't go over more than one line
+                        Some('"') => { self.pos += 1; break; },
+                        Some('\\') if matches!(self.chars.get(self.pos + 1), Some('"') | Some('\\')) => {
+                            value.push(self.chars[self.pos + 1]);
+                            self.pos += 2;
+                        },
+                        Some('\n') | Some('\r') | None => return Err(PgnError { line, column, kind: PgnErrorKind::UnterminatedString }),
+                        Some(other) => { value.push(other); self.pos += 1; }
+                    }
+                }
+                Token::Str(value)
+            },
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match self.bump()? {
+                        Some('}') => break,
+                        Some(other) => comment.push(other),
+                        None => return Err(PgnError { line, column, kind: PgnErrorKind::UnterminatedComment })
+                    }
+                }
+                Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" "))
+            },
+            ';' => {
+                let comment: String = self.chars[self.pos..].iter().collect();
+                self.skip_line();
+                Token::Comment(comment.trim().to_string())
+            },
+            '$' => {
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                Token::Nag(digits.parse().map_err(|_| PgnError { line, column, kind: PgnErrorKind::InvalidNag(format!("${}", digits)) })?)
+            },
+            '!' | '?' => {
+                let annotation = format!("{}{}", c, self.take_while(|c| c == '!' || c == '?'));
+                Token::Nag(annotation_nag(&annotation).ok_or(PgnError { line, column, kind: PgnErrorKind::InvalidNag(annotation) })?)
+            },
+            '.' => { // "..." without a number in front
+                self.take_while(|c| c == '.');
+                return self.next_token();
+            },
+            c if c.is_ascii_alphanumeric() => {
+                let symbol = format!("{}{}", c, self.take_while(is_symbol_char));
+                if symbol.chars().all(|c| c.is_ascii_digit()) { // "12." or "12..." (or only "12")
+                    self.take_while(|c| c == '.');
+                    Token::MoveNumber
+                } else if let Some(result) = GameResult::from_pgn(&symbol) {
+                    Token::Result(result)
+                } else {
+                    Token::Symbol(symbol)
+                }
+            },
+            other => return Err(PgnError { line, column, kind: PgnErrorKind::UnexpectedChar(other) })
+        };
+        Ok(Some(Spanned { token, line, column }))
+    }
+
+    // Only looks at the current line (no token goes over two lines)
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.pos < self.chars.len() && keep(self.chars[self.pos]) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '#' | '=' | ':' | '-' | '/')
+}
+
+// The move annotations that have a nag number of their own
+fn annotation_nag(annotation: &str) -> Option<u8> {
+    match annotation {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None
+    }
+}
+
+
+
+// Reads the games one by one: for game in PgnReader::new(BufReader::new(file)) { ... }
+// After a game with an error the reader goes on with the next game.
+pub struct PgnReader<R: BufRead> {
+    lexer: Lexer<R>,
+    peeked: Option<Spanned>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        PgnReader { lexer: Lexer { reader, chars: Vec::new(), pos: 0, line: 0 }, peeked: None }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Spanned>, PgnError> {
+        match self.peeked.take() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.next_token()
+        }
+    }
+
+    fn unexpected(spanned: &Spanned) -> PgnError {
+        let text = match &spanned.token {
+            Token::TagOpen => "[".to_string(),
+            Token::TagClose => "]".to_string(),
+            Token::Str(value) => format!("\"{}\"", value),
+            Token::Symbol(symbol) => symbol.clone(),
+            Token::Comment(comment) => format!("{{{}}}", comment),
+            Token::VariationOpen => "(".to_string(),
+            Token::VariationClose => ")".to_string(),
+            Token::Nag(nag) => format!("${}", nag),
+            Token::MoveNumber => "move number".to_string(),
+            Token::Result(result) => result.to_pgn().to_string(),
+        };
+        PgnError { line: spanned.line, column: spanned.column, kind: PgnErrorKind::UnexpectedToken(text) }
+    }
+
+    pub fn read_game(&mut self) -> Result<Option<Game>, PgnError> {
+        let mut game = Game::new();
+
+        // Tags
+        let mut spanned = match self.next_token()? {
+            Some(spanned) => spanned,
+            None => return Ok(None)
+        };
+        while spanned.token == Token::TagOpen {
+            let name = match self.next_token()? {
+                Some(Spanned { token: Token::Symbol(name), .. }) => name,
+                Some(other) => return Err(Self::unexpected(&other)),
+                None => return Err(self.lexer.error(PgnErrorKind::MissingResult))
+            };
+            let value = match self.next_token()? {
+                Some(Spanned { token: Token::Str(value), .. }) => value,
+                Some(other) => return Err(Self::unexpected(&other)),
+                None => return Err(self.lexer.error(PgnErrorKind::MissingResult))
+            };
+            match self.next_token()? {
+                Some(Spanned { token: Token::TagClose, .. }) => (),
+                Some(other) => return Err(Self::unexpected(&other)),
+                None => return Err(self.lexer.error(PgnErrorKind::MissingResult))
+            }
+            game.tags.push((name, value));
+
+            spanned = match self.next_token()? {
+                Some(spanned) => spanned,
+                None => return Err(self.lexer.error(PgnErrorKind::MissingResult))
+            };
+        }
+        let (line, column) = (spanned.line, spanned.column);
+        self.peeked = Some(spanned);
+
+        // Moves
+        let position = game.start_position().map_err(|error| PgnError { line, column, kind: PgnErrorKind::InvalidFen(error) })?;
+        let (moves, result) = self.read_line(position, 0)?;
+        game.moves = moves;
+        game.result = result.expect("read_game: the main line always ends with a result");
+        Ok(Some(game))
+    }
+
+    // Reads moves until the result (main line) or ')' (variation)
+    fn read_line(&mut self, mut position: Position, depth: usize) -> Result<(Vec<PgnMove>, Option<GameResult>), PgnError> {
+        let mut moves: Vec<PgnMove> = Vec::new();
+        let mut before_last_move: Option<Position> = None; // variations start from here
+        let mut pending_comment: Option<String> = None;
+
+        loop {
+            let spanned = match self.next_token()? {
+                Some(spanned) => spanned,
+                None => return Err(self.lexer.error(if depth > 0 { PgnErrorKind::UnclosedVariation } else { PgnErrorKind::MissingResult }))
+            };
+
+            match spanned.token.clone() {
+                Token::MoveNumber => (),
+                Token::Symbol(san) => {
+                    let mov = position.parse_san(&san).map_err(|error| PgnError {
+                        line: spanned.line, column: spanned.column, kind: PgnErrorKind::IllegalMove { san: san.clone(), error }
+                    })?;
+                    before_last_move = Some(position.clone());
+                    position.make_move(mov);
+
+                    let mut pgn_move = PgnMove::new(mov);
+                    pgn_move.starting_comment = pending_comment.take();
+                    moves.push(pgn_move);
+                },
+                Token::Nag(nag) => match moves.last_mut() {
+                    Some(last) => last.nags.push(nag),
+                    None => return Err(Self::unexpected(&spanned))
+                },
+                Token::Comment(comment) => {
+                    let target = match moves.last_mut() {
+                        Some(last) => &mut last.comment,
+                        None => &mut pending_comment
+                    };
+                    match target {
+                        Some(old) => { old.push(' '); old.push_str(&comment); },
+                        None => *target = Some(comment)
+                    }
+                },
+                Token::VariationOpen => {
+                    let start = match &before_last_move {
+                        Some(start) => start.clone(),
+                        None => return Err(Self::unexpected(&spanned))
+                    };
+                    let (variation, _) = self.read_line(start, depth + 1)?;
+                    moves.last_mut().expect("read_line: there is a move when there is a position before it").variations.push(variation);
+                },
+                Token::VariationClose if depth > 0 => return Ok((moves, None)),
+                Token::Result(result) if depth == 0 => return Ok((moves, Some(result))),
+                Token::TagOpen if depth == 0 => { // a new game started, keep the '[' for it
+                    self.peeked = Some(spanned.clone());
+                    return Err(PgnError { line: spanned.line, column: spanned.column, kind: PgnErrorKind::MissingResult });
+                },
+                _ => return Err(Self::unexpected(&spanned))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_game() {
+            Ok(game) => game.map(Ok),
+            Err(error) => {
+                if self.peeked.is_none() && self.lexer.skip_to_next_game().is_err() {
+                    self.lexer.skip_line();
+                }
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+// All the games in a string
+pub fn read_games(pgn: &str) -> Vec<Result<Game, PgnError>> {
+    PgnReader::new(pgn.as_bytes()).collect()
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GAMES: &str = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+[Annotator "Someone \"quoted\""]
+
+{Opening comment} 1. e4 e5 2. Nf3 Nc6 3. Bb5 {This opening is called the Ruy Lopez.} 3... a6
+4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7
+11. c4 c6 12. cxb5 axb5 13. Nc3 Bb7 14. Bg5 b4 15. Nb1 h6 16. Bh4 c5 17. dxe5
+Nxe4 18. Bxe7 Qxe7 19. exd6 Qf6 20. Nbd2 Nxd6 21. Nc4 Nxc4 22. Bxc4 Nb6
+23. Ne5 Rae8 24. Bxf7+ Rxf7 25. Nxf7 Rxe1+ 26. Qxe1 Kxf7 27. Qe3 Qg5 28. Qxg5
+hxg5 29. b3 Ke6 30. a3 Kd6 31. axb4 cxb4 32. Ra5 Nd5 33. f3 Bc8 34. Kf2 Bf5
+35. Ra7 g6 36. Ra6+ Kc5 37. Ke1 Nf4 38. g3 Nxh3 39. Kd2 Kb5 40. Rd6 Kc5 41. Ra6
+Nf2 42. g4 Bd3 43. Re6 1/2-1/2
+
+% an escaped line, ignored
+[Event "Variations"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "?"]
+[Black "?"]
+[Result "1-0"]
+
+1. e4! e5 $1 (1... c5 {Sicilian} 2. Nf3 (2. Nc3 Nc6) 2... d6) (1... e6?!) 2. Nf3 ; rest of line
+Nc6 3. Bc4 Nd4?? 4. Nxe5 Qg5 5. Nxf7 Qxg2 6. Rf1 Qxe4+ 7. Be2 Nf3# 1-0
+
+[Event "From a position"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40"]
+[SetUp "1"]
+
+40... Kd7 41. e4 *
+"#;
+
+    #[test]
+    fn test_read_games() {
+        let games: Vec<Game> = read_games(GAMES).into_iter().map(|game| game.unwrap()).collect();
+        assert_eq!(games.len(), 3);
+
+        let fischer = &games[0];
+        assert_eq!(fischer.tag("White"), Some("Fischer, Robert J."));
+        assert_eq!(fischer.tag("Annotator"), Some("Someone \"quoted\""));
+        assert_eq!(fischer.result, GameResult::Draw);
+        assert_eq!(fischer.moves.len(), 85);
+        assert_eq!(fischer.moves[0].starting_comment.as_deref(), Some("Opening comment"));
+        assert_eq!(fischer.moves[4].comment.as_deref(), Some("This opening is called the Ruy Lopez."));
+        assert_eq!(fischer.end_position().unwrap().write_fen(), "8/8/4R1p1/2k3p1/1p4P1/1P1b1P2/3K1n2/8 b - - 2 43");
+
+        let variations = &games[1];
+        assert_eq!(variations.result, GameResult::WhiteWins);
+        assert_eq!(variations.moves[0].nags, vec![1]);
+        assert_eq!(variations.moves[1].nags, vec![1]);
+        assert_eq!(variations.moves[1].variations.len(), 2);
+        let sicilian = &variations.moves[1].variations[0];
+        assert_eq!(sicilian.len(), 3);
+        assert_eq!(sicilian[0].comment.as_deref(), Some("Sicilian"));
+        assert_eq!(sicilian[1].variations[0].len(), 2);
+        assert_eq!(variations.moves[1].variations[1][0].nags, vec![6]);
+        assert_eq!(variations.moves[2].comment.as_deref(), Some("rest of line"));
+        assert_eq!(variations.moves[5].nags, vec![4]);
+        assert!(variations.end_position().unwrap().outcome().is_some());
+
+        let from_fen = &games[2];
+        assert_eq!(from_fen.tag("Event"), Some("From a position"));
+        assert_eq!(from_fen.moves.len(), 2);
+        assert_eq!(from_fen.result, GameResult::Unknown);
+    }
+
+    #[test]
+    fn test_write_and_read_again() {
+        for game in read_games(GAMES) {
+            let game = game.unwrap();
+            let written = game.to_pgn().unwrap();
+
+            for line in written.lines() {
+                assert!(line.len() <= MAX_LINE_LENGTH, "{}", line);
+            }
+            let lines: Vec<&str> = written.lines().collect();
+            assert!(lines[0].starts_with("[Event "));
+            assert!(lines[6].starts_with("[Result "));
+
+            let again = read_games(&written);
+            assert_eq!(again.len(), 1);
+            let again = again[0].as_ref().unwrap();
+            assert_eq!(again.moves, game.moves);
+            assert_eq!(again.result, game.result);
+            for (name, value) in &game.tags {
+                assert_eq!(again.tag(name), Some(value.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_format() {
+        let games = read_games(GAMES);
+        let written = games[1].as_ref().unwrap().to_pgn().unwrap();
+        let movetext: Vec<&str> = written.lines().skip_while(|line| !line.is_empty()).skip(1).collect();
+        assert_eq!(movetext.join("\n"),
+"1. e4 $1 e5 $1 (1... c5 {Sicilian} 2. Nf3 (2. Nc3 Nc6) 2... d6) (1... e6 $6)
+2. Nf3 {rest of line} 2... Nc6 3. Bc4 Nd4 $4 4. Nxe5 Qg5 5. Nxf7 Qxg2 6. Rf1
+Qxe4+ 7. Be2 Nf3# 1-0");
+
+        let mut empty = Game::new();
+        empty.set_tag("White", "Me");
+        assert_eq!(empty.to_pgn().unwrap(), "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"Me\"]\n[Black \"?\"]\n[Result \"*\"]\n\n*\n");
+
+        // A broken FEN tag or a move that can't be played is an error, not a panic
+        let mut bad_fen = empty.clone();
+        bad_fen.set_tag("FEN", "garbage");
+        assert!(matches!(bad_fen.to_pgn(), Err(PgnError { kind: PgnErrorKind::InvalidFen(_), .. })));
+
+        let mut illegal = Game::new();
+        let position = Position::new(None);
+        illegal.moves.push(PgnMove::new(position.parse_uci("e2e4").unwrap()));
+        illegal.moves.push(PgnMove::new(position.parse_uci("d2d4").unwrap())); // white again
+        let error = illegal.to_pgn().unwrap_err();
+        assert_eq!(error.kind, PgnErrorKind::NotLegal("d2d4".to_string()));
+        assert_eq!(error.to_string(), "move 'd2d4' is not legal in the position it is played from");
+    }
+
+    #[test]
+    fn test_errors() {
+        let pgn = "[Event \"Bad move\"]\n\n1. e4 e5 2. Ke3 Nc6 *\n\n[Event \"Good\"]\n\n1. d4 d5 1-0\n";
+        let games = read_games(pgn);
+        assert_eq!(games.len(), 2);
+        let error = games[0].as_ref().unwrap_err();
+        assert_eq!((error.line, error.column), (3, 13));
+        assert!(matches!(&error.kind, PgnErrorKind::IllegalMove { san, .. } if san == "Ke3"));
+        assert_eq!(games[1].as_ref().unwrap().tag("Event"), Some("Good"));
+
+        let no_result = read_games("[Event \"A\"]\n\n1. e4 e5\n\n[Event \"B\"]\n\n1. d4 *\n");
+        assert_eq!(no_result.len(), 2);
+        assert_eq!(no_result[0].as_ref().unwrap_err().kind, PgnErrorKind::MissingResult);
+        assert_eq!(no_result[1].as_ref().unwrap().tag("Event"), Some("B"));
+
+        let comment = read_games("1. e4 {never closed *\n");
+        assert_eq!(comment[0].as_ref().unwrap_err(), &PgnError { line: 1, column: 7, kind: PgnErrorKind::UnterminatedComment });
+
+        let variation = read_games("1. e4 (1. d4 *\n");
+        assert_eq!(variation[0].as_ref().unwrap_err().kind, PgnErrorKind::UnexpectedToken("*".to_string()));
+
+        let tag = read_games("[Event \"no end]\n1. e4 *\n");
+        assert_eq!(tag[0].as_ref().unwrap_err(), &PgnError { line: 1, column: 8, kind: PgnErrorKind::UnterminatedString });
+
+        // The rest of the broken game's tags are not read as a game of their own
+        let tags = read_games("[Event \"no end]\n[Site \"x\"]\n\n1. e4 *\n\n[Event \"B\"]\n\n1. d4 *\n");
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].as_ref().unwrap_err().kind, PgnErrorKind::UnterminatedString);
+        assert_eq!(tags[1].as_ref().unwrap().tag("Event"), Some("B"));
+        // A bad move on the last line of a game, with the next game right after it
+        let moves = read_games("[Event \"A\"]\n\n1. e4 e5 2. Ke3 *\n[Event \"B\"]\n[Site \"y\"]\n\n1. d4 *\n");
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[1].as_ref().unwrap().tag("Site"), Some("y"));
+
+        let fen = read_games("[FEN \"not a fen\"]\n\n1. e4 *\n");
+        assert!(matches!(fen[0].as_ref().unwrap_err().kind, PgnErrorKind::InvalidFen(_)));
+        let pawn = read_games("[FEN \"P3k3/8/8/8/8/8/8/4K3 w - - 0 1\"]\n\n1. Kd2 *\n");
+        assert!(matches!(pawn[0].as_ref().unwrap_err().kind, PgnErrorKind::InvalidFen(FenError::PawnOnBackRank(_))));
+    }
+}
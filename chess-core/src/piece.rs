@@ -85,6 +85,24 @@ impl PieceIndex {
         })
     }
 
+    // The chess symbols, ♔ for the white king and ♚ for the black one
+    pub fn to_unicode(self) -> char {
+        match self {
+            PieceIndex::WhitePawn   => '♙',
+            PieceIndex::WhiteKnight => '♘',
+            PieceIndex::WhiteBishop => '♗',
+            PieceIndex::WhiteRook   => '♖',
+            PieceIndex::WhiteQueen  => '♕',
+            PieceIndex::WhiteKing   => '♔',
+            PieceIndex::BlackPawn   => '♟',
+            PieceIndex::BlackKnight => '♞',
+            PieceIndex::BlackBishop => '♝',
+            PieceIndex::BlackRook   => '♜',
+            PieceIndex::BlackQueen  => '♛',
+            PieceIndex::BlackKing   => '♚',
+        }
+    }
+
     #[inline] pub fn index(self)-> usize{
         self as usize
     }
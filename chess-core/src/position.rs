@@ -10,7 +10,7 @@ use crate::bitboard_consts::{self, CORNERS};
 
 
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Color {White, Black}
 
 impl Not for Color {
@@ -42,11 +42,23 @@ impl Position {
     }
 
     pub fn push(&mut self) {
-        self.history.push(self.current.clone());
+        self.history.push(self.current);
     }
 
+    // Goes back to the last pushed snapshot
     pub fn pop(&mut self){
-        self.history.pop().expect("You can't remove element from position history (it is probably empty)");
+        self.current = self.history.pop().expect("You can't remove element from position history (it is probably empty)");
+    }
+
+    // Takes back the last move made with make_move, so the position is exactly as it was before that move
+    pub fn unmake_move(&mut self) -> Result<(), UnmakeError>{
+        match self.history.pop() {
+            Some(previous) => {
+                self.current = previous;
+                Ok(())
+            },
+            None => Err(UnmakeError::NoMoveToUnmake)
+        }
     }
 
 
@@ -55,6 +67,23 @@ impl Position {
 
 
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnmakeError {
+    NoMoveToUnmake, // the history is empty, so there is nothing to go back to
+}
+
+impl std::fmt::Display for UnmakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnmakeError::NoMoveToUnmake => write!(f, "unmake_move was called, but no move has been made in this position"),
+        }
+    }
+}
+
+impl std::error::Error for UnmakeError {}
+
+
+
 
 
 
@@ -66,9 +95,59 @@ pub struct Snapshot {
     pub en_passant: Option<Square>,  
     pub halfmove_clock: u16,             
     pub fullmove_number: u16,
-    //zobrist_key:     u64,  // TODO look up and make this later (I'm in neeed for speeed)
+    pub zobrist_key: u64, // kept up to date by make_move, see zobrist.rs
 }
 
 
 
 
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{random_walk, test_rng};
+
+    #[test]
+    fn test_unmake_move_walks_back_to_start(){
+        let mut rng = test_rng();
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"] {
+            for _ in 0..10{
+                let start = Position::new(Some(fen));
+                let mut position = start.clone();
+                let mut seen = Vec::new();
+                random_walk(&mut position, 80, &mut rng, |position| seen.push(position.current));
+
+                while let Some(expected) = seen.pop(){
+                    assert_eq!(position.current, expected);
+                    if seen.is_empty(){
+                        break;
+                    }
+                    position.unmake_move().unwrap();
+                }
+                assert_eq!(position, start);
+                assert_eq!(position.write_fen(), start.write_fen());
+            }
+        }
+    }
+
+    #[test]
+    fn test_unmake_without_moves(){
+        let mut position = Position::new(None);
+        assert_eq!(position.unmake_move(), Err(UnmakeError::NoMoveToUnmake));
+        assert_eq!(position, Position::new(None));
+    }
+
+    #[test]
+    fn test_push_pop(){
+        let mut position = Position::new(None);
+        position.push();
+        let mov = *position.legal_moves().get(0).unwrap();
+        position.make_move(mov);
+        position.unmake_move().unwrap();
+        position.pop();
+        assert_eq!(position, Position::new(None));
+    }
+}
@@ -0,0 +1,302 @@
+use std::fmt;
+
+use crate::kastling::Imposter;
+use crate::moves::{BitMove, MoveList};
+use crate::piece::{Piece, PieceIndex};
+use crate::position::{Color, Position};
+use crate::square::Square;
+
+
+
+// Standard Algebraic Notation, the "Nf3", "exd5", "O-O", "e8=Q+" way of writing moves that humans and PGN use
+
+
+
+fn piece_letter(piece: Piece) -> Option<char> {
+    match piece {
+        Piece::Pawn => None,
+        _ => Some(PieceIndex::from_piece(piece, Color::White).to_fen_char()) // the upper case fen letter
+    }
+}
+
+fn piece_from_letter(letter: char) -> Option<Piece> {
+    match letter {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None
+    }
+}
+
+
+
+impl Position {
+    // The move must be legal in this position
+    pub fn to_san(&self, mov: BitMove) -> String {
+        let mut san = String::new();
+
+        match mov.get_castle_side() {
+            Some(Imposter::King) => san.push_str("O-O"),
+            Some(Imposter::Queen) => san.push_str("O-O-O"),
+            None => {
+                let piece = mov.get_piece(&self.current.bitboards).to_piece();
+                let from = mov.get_start_square();
+                let to = mov.get_end_square();
+
+                match piece_letter(piece) {
+                    Some(letter) => {
+                        san.push(letter);
+                        san.push_str(&self.disambiguation(mov, piece));
+                    },
+                    None => if mov.is_capture() {
+                        san.push(from.file().to_char());
+                    }
+                }
+
+                if mov.is_capture() {
+                    san.push('x');
+                }
+                san.push_str(&to.square_str());
+
+                if let Some(promo_piece) = mov.get_premotion_piece() {
+                    san.push('=');
+                    san.push(piece_letter(promo_piece).expect("to_san: can't promote to a pawn"));
+                }
+            }
+        }
+
+        let mut after = self.clone();
+        after.make_move(mov);
+        if after.is_in_check() {
+            if after.legal_moves().size() == 0 {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        san
+    }
+
+    // The file, rank or both of the start square, if other pieces of the same type can go to the same square
+    fn disambiguation(&self, mov: BitMove, piece: Piece) -> String {
+        let from = mov.get_start_square();
+        let to = mov.get_end_square();
+
+        let mut legal = MoveList::new_empty();
+        self.fill_legal(&mut legal);
+
+        let others: Vec<Square> = legal.iter()
+            .filter(|other| other.get_end_square() == to && other.get_start_square() != from)
+            .filter(|other| other.get_piece(&self.current.bitboards).to_piece() == piece)
+            .map(|other| other.get_start_square())
+            .collect();
+
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|other| other.file().to_char() != from.file().to_char()) {
+            from.file().to_char().to_string()
+        } else if others.iter().all(|other| other.rank().to_char() != from.rank().to_char()) {
+            from.rank().to_char().to_string()
+        } else {
+            from.square_str()
+        }
+    }
+
+    // Finds the legal move the SAN string means. Accepts some common sloppy forms as well:
+    // "0-0" for castling, "e8Q"/"e8=Q"/"e8q" for promotions, a missing "x" on captures and trailing "+#!?"
+    pub fn parse_san(&self, san: &str) -> Result<BitMove, SanError> {
+        let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        if trimmed.is_empty() {
+            return Err(SanError::Empty);
+        }
+
+        let mut legal = MoveList::new_empty();
+        self.fill_legal(&mut legal);
+
+        // Castling
+        let castle_side = match trimmed {
+            "O-O" | "0-0" => Some(Imposter::King),
+            "O-O-O" | "0-0-0" => Some(Imposter::Queen),
+            _ => None
+        };
+        if let Some(side) = castle_side {
+            return legal.iter().copied()
+                .find(|mov| mov.get_castle_side() == Some(side))
+                .ok_or_else(|| SanError::IllegalMove(san.to_string()));
+        }
+
+        let mut chars: Vec<char> = trimmed.chars().collect();
+
+        // Piece letter (a pawn has none)
+        let piece = match chars.first().and_then(|c| piece_from_letter(*c)) {
+            Some(piece) => {
+                chars.remove(0);
+                piece
+            },
+            None => Piece::Pawn
+        };
+
+        // Promotion at the end, with or without '=' (the destination ends with a digit, so a letter there is the promotion)
+        let mut promotion = None;
+        if piece == Piece::Pawn && chars.last().is_some_and(|c| c.is_ascii_alphabetic()) {
+            let letter = chars.pop().expect("parse_san: checked that there is a last char").to_ascii_uppercase();
+            promotion = match piece_from_letter(letter) {
+                Some(Piece::King) | None => return Err(SanError::InvalidSyntax(san.to_string())),
+                promo_piece => promo_piece
+            };
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        // Destination is the last two characters
+        if chars.len() < 2 {
+            return Err(SanError::InvalidSyntax(san.to_string()));
+        }
+        let destination: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = destination.parse::<Square>().map_err(|_| SanError::InvalidSyntax(san.to_string()))?;
+
+        // What is left is the disambiguation and maybe an 'x'
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            match c {
+                'a'..='h' => from_file = Some(c),
+                '1'..='8' => from_rank = Some(c),
+                'x' | 'X' | ':' | '-' => (),
+                _ => return Err(SanError::InvalidSyntax(san.to_string()))
+            }
+        }
+
+        let candidates: Vec<BitMove> = legal.iter().copied()
+            .filter(|mov| mov.get_castle_side().is_none())
+            .filter(|mov| mov.get_end_square() == to)
+            .filter(|mov| mov.get_piece(&self.current.bitboards).to_piece() == piece)
+            .filter(|mov| mov.get_premotion_piece() == promotion)
+            .filter(|mov| from_file.is_none_or(|file| mov.get_start_square().file().to_char() == file))
+            .filter(|mov| from_rank.is_none_or(|rank| mov.get_start_square().rank().to_char() == rank))
+            .collect();
+
+        match candidates.len() {
+            0 => Err(SanError::IllegalMove(san.to_string())),
+            1 => Ok(candidates[0]),
+            _ => Err(SanError::AmbiguousMove(san.to_string()))
+        }
+    }
+}
+
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    Empty,
+    InvalidSyntax(String),
+    IllegalMove(String),   // could be read, but no legal move fits
+    AmbiguousMove(String), // more than one legal move fits
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Empty => write!(f, "empty move"),
+            SanError::InvalidSyntax(san) => write!(f, "'{}' is not a move in standard algebraic notation", san),
+            SanError::IllegalMove(san) => write!(f, "'{}' is not a legal move in this position", san),
+            SanError::AmbiguousMove(san) => write!(f, "'{}' fits more than one legal move", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn san_of(fen: &str, from: Square, to: Square) -> String {
+        let position = Position::new(Some(fen));
+        let mov = position.legal_moves().iter().copied()
+            .find(|mov| mov.get_start_square() == from && mov.get_end_square() == to)
+            .expect("test move is not legal");
+        position.to_san(mov)
+    }
+
+    #[test]
+    fn test_to_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, Square::E2, Square::E4), "e4");
+        assert_eq!(san_of(start, Square::G1, Square::F3), "Nf3");
+
+        // disambiguation by file, rank and both
+        assert_eq!(san_of("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", Square::A1, Square::D1), "Rad1");
+        assert_eq!(san_of("4k3/8/8/R7/8/8/4K3/R7 w - - 0 1", Square::A1, Square::A3), "R1a3");
+        assert_eq!(san_of("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", Square::A1, Square::B2), "Qa1b2");
+        // a pinned knight can't go there, so no disambiguation
+        assert_eq!(san_of("4k3/8/8/8/7b/2N3N1/8/4K3 w - - 0 1", Square::C3, Square::E4), "Ne4");
+        assert_eq!(san_of("4k3/8/8/8/8/2N3N1/8/4K3 w - - 0 1", Square::C3, Square::E4), "Nce4");
+
+        // captures, en passant and promotions
+        assert_eq!(san_of("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", Square::E4, Square::D5), "exd5");
+        assert_eq!(san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", Square::E5, Square::D6), "exd6");
+        let promo = Position::new(Some("3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1"));
+        let sans: Vec<String> = promo.legal_moves().iter().map(|mov| promo.to_san(*mov)).collect();
+        assert!(sans.contains(&"e8=Q+".to_string()));
+        assert!(sans.contains(&"exd8=N".to_string()));
+        assert!(sans.contains(&"exd8=Q+".to_string()));
+
+        // castling, check and mate
+        assert_eq!(san_of("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", Square::E1, Square::G1), "O-O");
+        assert_eq!(san_of("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", Square::E8, Square::C8), "O-O-O");
+        assert_eq!(san_of("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", Square::D8, Square::H4), "Qh4#");
+        assert_eq!(san_of("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", Square::A1, Square::A8), "Ra8+");
+    }
+
+    #[test]
+    fn test_san_round_trip() {
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+                    "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1"] {
+            let position = Position::new(Some(fen));
+            for mov in position.legal_moves().iter() {
+                let san = position.to_san(*mov);
+                assert_eq!(position.parse_san(&san), Ok(*mov), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_sloppy_san() {
+        let castle = Position::new(Some("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        assert_eq!(castle.to_san(castle.parse_san("0-0").unwrap()), "O-O");
+        assert_eq!(castle.to_san(castle.parse_san("0-0-0+").unwrap()), "O-O-O");
+
+        let promo = Position::new(Some("3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(promo.to_san(promo.parse_san("e8Q").unwrap()), "e8=Q+");
+        assert_eq!(promo.to_san(promo.parse_san("e8=Q").unwrap()), "e8=Q+");
+        assert_eq!(promo.to_san(promo.parse_san("ed8N").unwrap()), "exd8=N");
+        assert_eq!(promo.to_san(promo.parse_san("exd8b").unwrap()), "exd8=B");
+
+        let start = Position::new(None);
+        assert_eq!(start.to_san(start.parse_san("Nf3!?").unwrap()), "Nf3");
+        assert_eq!(start.to_san(start.parse_san("Ng1f3").unwrap()), "Nf3");
+        assert_eq!(start.to_san(start.parse_san("e4!!").unwrap()), "e4");
+
+        let capture = Position::new(Some("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1"));
+        assert_eq!(capture.to_san(capture.parse_san("ed5").unwrap()), "exd5");
+
+        assert_eq!(start.parse_san(""), Err(SanError::Empty));
+        assert_eq!(start.parse_san("e5"), Err(SanError::IllegalMove("e5".to_string())));
+        assert_eq!(start.parse_san("Zf3"), Err(SanError::InvalidSyntax("Zf3".to_string())));
+        assert_eq!(start.parse_san("O-O"), Err(SanError::IllegalMove("O-O".to_string())));
+        let rooks = Position::new(Some("4k3/8/8/8/8/8/4K3/R6R w - - 0 1"));
+        assert_eq!(rooks.parse_san("Rd1"), Err(SanError::AmbiguousMove("Rd1".to_string())));
+    }
+}
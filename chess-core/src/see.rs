@@ -0,0 +1,161 @@
+use crate::attack;
+use crate::board::Bitboard;
+use crate::moves::BitMove;
+use crate::piece::Piece;
+use crate::position::{Color, Position};
+use crate::square::Square;
+
+
+
+// Static exchange evaluation: what a move wins or loses in material if both sides keep capturing on the end square,
+// always with their least valuable piece, and each side may stop when capturing more would lose material.
+// Sliders behind the pieces that capture (x-rays) join in when the piece in front of them has been used.
+
+
+
+// Indexed by Piece as usize. The king is only ever the last piece to capture, so its value does not matter much
+pub const SEE_VALUES: [i32; 6] = [100, 300, 300, 500, 900, 20000];
+
+pub fn see_value(piece: Piece) -> i32 {
+    SEE_VALUES[piece as usize]
+}
+
+const PIECES_BY_VALUE: [Piece; 6] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King];
+
+impl Position {
+    // The material the side to move wins (or loses if negative) with the move, quiet moves are 0 or less
+    pub fn see(&self, mov: BitMove) -> i32 {
+        if mov.get_castle_side().is_some() {
+            return 0;
+        }
+
+        let boards = &self.current.bitboards;
+        let color = self.current.side_to_move;
+        let from = mov.get_start_square();
+        let to = mov.get_end_square();
+        let last_rank = to.to_coord().0 == 0 || to.to_coord().0 == 7;
+
+        let mut occupancy = boards.all_occupancy & !from.to_bitboard();
+
+        let mut gain = [0i32; 32];
+        gain[0] = if mov.is_en_passant() {
+            let captured_square = Square::from_coords(from.to_coord().0, to.to_coord().1).expect("see: en passant pawn is on the board");
+            occupancy &= !captured_square.to_bitboard();
+            see_value(Piece::Pawn)
+        } else {
+            boards.piece_on_square(to).map(|piece| see_value(piece.to_piece())).unwrap_or(0)
+        };
+
+        // The value of the piece standing on the square, which the next capture takes
+        let mut on_square = match mov.get_premotion_piece() {
+            Some(promo_piece) => {
+                gain[0] += see_value(promo_piece) - see_value(Piece::Pawn);
+                see_value(promo_piece)
+            },
+            None => see_value(mov.get_piece(boards).to_piece())
+        };
+
+        let straight = boards.pieces(Piece::Rook, Color::White) | boards.pieces(Piece::Rook, Color::Black)
+                     | boards.pieces(Piece::Queen, Color::White) | boards.pieces(Piece::Queen, Color::Black);
+        let diagonal = boards.pieces(Piece::Bishop, Color::White) | boards.pieces(Piece::Bishop, Color::Black)
+                     | boards.pieces(Piece::Queen, Color::White) | boards.pieces(Piece::Queen, Color::Black);
+
+        let mut attackers = self.attackers_to(to, occupancy) & occupancy;
+        let mut side = !color;
+        let mut depth = 0;
+
+        loop {
+            let side_attackers = attackers & boards.occupancy(side);
+            let Some((piece, mut attacker)) = PIECES_BY_VALUE.iter()
+                .map(|piece| (*piece, side_attackers & boards.pieces(*piece, side)))
+                .find(|(_, pieces)| !pieces.is_empty())
+            else {
+                break;
+            };
+
+            // The king can't capture into a square the other side still attacks
+            if piece == Piece::King && attackers.intersects(boards.occupancy(!side)) {
+                break;
+            }
+
+            depth += 1;
+            gain[depth] = on_square - gain[depth - 1];
+            on_square = see_value(piece);
+            if piece == Piece::Pawn && last_rank {
+                gain[depth] += see_value(Piece::Queen) - see_value(Piece::Pawn);
+                on_square = see_value(Piece::Queen);
+            }
+
+            // Take the piece off and let the sliders behind it in
+            let idx = attacker.pop_lsb().expect("see: the attacker bitboard is not empty");
+            occupancy &= !Bitboard::from(idx);
+            attackers |= (attack::rook_attacks(to, occupancy) & straight) | (attack::bishop_attacks(to, occupancy) & diagonal);
+            attackers &= occupancy;
+
+            side = !side;
+        }
+
+        // Each side only captures if it is better than stopping
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    // Does the move win at least `threshold` (see_ge(mov, 0) is "does not lose material")
+    pub fn see_ge(&self, mov: BitMove, threshold: i32) -> bool {
+        self.see(mov) >= threshold
+    }
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn see_of(fen: &str, uci: &str) -> i32 {
+        let position = Position::new(Some(fen));
+        position.see(position.parse_uci(uci).unwrap())
+    }
+
+    #[test]
+    fn test_see() {
+        // Free pawn
+        assert_eq!(see_of("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+        // Defended pawn
+        assert_eq!(see_of("4k3/8/3p4/4p3/8/8/8/4RK2 w - - 0 1", "e1e5"), -400);
+        // Two rooks against one, black does not take back
+        assert_eq!(see_of("4r1k1/8/8/4p3/8/8/4R3/4RK2 w - - 0 1", "e2e5"), 100);
+        // The bishop behind the pawn (x-ray) makes the exchange a pawn up
+        assert_eq!(see_of("4k3/8/2p5/3p4/4P3/5B2/8/4K3 w - - 0 1", "e4d5"), 100);
+        // Queen takes a defended knight
+        assert_eq!(see_of("4k3/8/4p3/3n4/8/8/8/3QK3 w - - 0 1", "d1d5"), 300 - 900);
+        // Quiet move to a square a pawn attacks
+        assert_eq!(see_of("4k3/8/8/4p3/8/8/8/3QK3 w - - 0 1", "d1d4"), -900);
+        // Quiet move to a safe square
+        assert_eq!(see_of("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "d1d4"), 0);
+        // En passant
+        assert_eq!(see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        // Promotion, and a promotion the rook takes back
+        assert_eq!(see_of("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+        assert_eq!(see_of("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), -100);
+        // The king takes back when nothing else defends
+        assert_eq!(see_of("8/8/5k2/4p3/8/8/8/4RK2 w - - 0 1", "e1e5"), -400);
+        // ... but not when the bishop on b2 also attacks e5
+        assert_eq!(see_of("8/8/5k2/4p3/8/8/1B6/4RK2 w - - 0 1", "e1e5"), 100);
+    }
+
+    #[test]
+    fn test_see_ge() {
+        let position = Position::new(Some("4k3/8/4p3/3n4/8/8/8/3QK3 w - - 0 1"));
+        let mov = position.parse_uci("d1d5").unwrap();
+        assert!(!position.see_ge(mov, 0));
+        assert!(position.see_ge(mov, -600));
+        assert!(!position.see_ge(mov, -599));
+    }
+}
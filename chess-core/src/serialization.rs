@@ -0,0 +1,490 @@
+use std::str::FromStr;
+
+use ::serde::de::{DeserializeOwned, Error};
+use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::board::{Bitboard, Bitboards};
+use crate::builder::check_pieces;
+use crate::fen_string::{read_board, write_board};
+use crate::kastling::{Castling, CastlingSide, Imposter};
+use crate::moves::{BitMove, MoveType};
+use crate::piece::{Piece, PieceIndex};
+use crate::position::{Color, Position, Snapshot};
+use crate::square::Square;
+
+
+
+// serde support (the "serde" feature).
+// Formats that are read by people (JSON, TOML ...) get strings: positions and snapshots as FEN, moves as UCI,
+// squares as "e4", pieces as their FEN letter, a board as the board field of a FEN and castling as "KQkq".
+// Binary formats get the compact forms: numbers and tuples of numbers.
+// Use #[serde(with = "chess_core::serialization::compact")] (or readable) on a field to always get one of them.
+//
+// Positions are written without their history, only the current snapshot.
+// A UCI string does not say if a move captures, castles or takes en passant, so a move read back from one only has
+// its squares and promotion right, Position::find_move gives the full legal move. The compact form keeps everything.
+
+
+
+// The string form
+pub trait Readable: Sized {
+    fn to_readable(&self) -> String;
+    fn from_readable(text: &str) -> Result<Self, String>;
+}
+
+// The compact form, checked when it is read so a broken file can't make an impossible board
+pub trait Compact: Sized {
+    type Repr: Serialize + DeserializeOwned;
+    fn to_compact(&self) -> Self::Repr;
+    fn from_compact(repr: Self::Repr) -> Result<Self, String>;
+}
+
+macro_rules! impl_serde {
+    ($($type:ty),*) => {$(
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    readable::serialize(self, serializer)
+                } else {
+                    compact::serialize(self, serializer)
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                if deserializer.is_human_readable() {
+                    readable::deserialize(deserializer)
+                } else {
+                    compact::deserialize(deserializer)
+                }
+            }
+        }
+    )*};
+}
+
+impl_serde!(Square, PieceIndex, BitMove, Castling, Bitboards, Snapshot, Position);
+
+pub mod readable {
+    use super::*;
+
+    pub fn serialize<T: Readable, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_readable())
+    }
+
+    pub fn deserialize<'de, T: Readable, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        T::from_readable(&text).map_err(D::Error::custom)
+    }
+}
+
+pub mod compact {
+    use super::*;
+
+    pub fn serialize<T: Compact, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        value.to_compact().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: Compact, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let repr = T::Repr::deserialize(deserializer)?;
+        T::from_compact(repr).map_err(D::Error::custom)
+    }
+}
+
+
+
+impl Readable for Square {
+    fn to_readable(&self) -> String {
+        self.square_str()
+    }
+
+    fn from_readable(text: &str) -> Result<Self, String> {
+        Square::from_str(text).map_err(|_| format!("'{}' is not a square", text))
+    }
+}
+
+impl Compact for Square {
+    type Repr = u8;
+
+    fn to_compact(&self) -> u8 {
+        self.index()
+    }
+
+    fn from_compact(index: u8) -> Result<Self, String> {
+        Square::from_idx(index).ok_or_else(|| format!("{} is not a square index", index))
+    }
+}
+
+impl Readable for PieceIndex {
+    fn to_readable(&self) -> String {
+        self.to_fen_char().to_string()
+    }
+
+    fn from_readable(text: &str) -> Result<Self, String> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(letter), None) => PieceIndex::from_fen_char(letter),
+            _ => None,
+        }.ok_or_else(|| format!("'{}' is not a piece", text))
+    }
+}
+
+impl Compact for PieceIndex {
+    type Repr = u8;
+
+    fn to_compact(&self) -> u8 {
+        self.index() as u8
+    }
+
+    // try_from can't be used, it gives the default (WhitePawn) for every number that is not a piece
+    fn from_compact(index: u8) -> Result<Self, String> {
+        match index {
+            0..12 => Ok(PieceIndex::try_from(index as usize).expect("PieceIndex::from_compact: checked above")),
+            _ => Err(format!("{} is not a piece index", index)),
+        }
+    }
+}
+
+impl Readable for BitMove {
+    fn to_readable(&self) -> String {
+        self.to_uci()
+    }
+
+    fn from_readable(text: &str) -> Result<Self, String> {
+        let invalid = || format!("'{}' is not a UCI move", text);
+        if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+            return Err(invalid());
+        }
+        let from = Square::from_str(&text[0..2]).map_err(|_| invalid())?;
+        let to = Square::from_str(&text[2..4]).map_err(|_| invalid())?;
+        let move_type = match text[4..].chars().next() {
+            None => MoveType::Quiet,
+            Some(letter) => match PieceIndex::from_fen_char(letter).map(|piece| Piece::from_piece_index(&piece)) {
+                Some(piece @ (Piece::Knight | Piece::Bishop | Piece::Rook | Piece::Queen)) => MoveType::Promotion(piece),
+                _ => return Err(invalid()),
+            },
+        };
+        Ok(BitMove::new(from, to, false, move_type))
+    }
+}
+
+impl Compact for BitMove {
+    type Repr = u16;
+
+    fn to_compact(&self) -> u16 {
+        self.to_bits()
+    }
+
+    fn from_compact(bits: u16) -> Result<Self, String> {
+        Ok(BitMove::from_bits(bits))
+    }
+}
+
+// "KQkq" or "-", in chess960 every right is followed by the file of its rook ("KhQbkhqb")
+impl Readable for Castling {
+    fn to_readable(&self) -> String {
+        let mut text = String::new();
+        for side in CastlingSide::ALL {
+            if self.can_castle(side) {
+                let letter = if Imposter::from_castling_side(side) == Imposter::King { 'k' } else { 'q' };
+                text.push(if side.color() == Color::White { letter.to_ascii_uppercase() } else { letter });
+                if self.chess960 {
+                    text.push((b'a' + self.rook_file(side)) as char);
+                }
+            }
+        }
+        if text.is_empty() {
+            text.push('-');
+        }
+        text
+    }
+
+    fn from_readable(text: &str) -> Result<Self, String> {
+        let invalid = || format!("'{}' is not a castling field", text);
+        let mut castling = Castling::new();
+        if text == "-" {
+            return Ok(castling);
+        }
+
+        let mut chars = text.chars().peekable();
+        while let Some(letter) = chars.next() {
+            let side = match letter {
+                'K' => CastlingSide::WK,
+                'Q' => CastlingSide::WQ,
+                'k' => CastlingSide::BK,
+                'q' => CastlingSide::BQ,
+                _ => return Err(invalid()),
+            };
+            if castling.can_castle(side) {
+                return Err(invalid());
+            }
+            match chars.next_if(|file| ('a'..='h').contains(file)) {
+                Some(file) => {
+                    castling.chess960 = true;
+                    castling.add_castle_right_with_rook(side, file as u8 - b'a');
+                },
+                None => castling.add_castle_right(side),
+            }
+        }
+        Ok(castling)
+    }
+}
+
+impl Compact for Castling {
+    type Repr = (u8, [u8; 4], bool);
+
+    fn to_compact(&self) -> Self::Repr {
+        (self.rights, self.rook_files, self.chess960)
+    }
+
+    fn from_compact((rights, rook_files, chess960): Self::Repr) -> Result<Self, String> {
+        if rights > 0b1111 || rook_files.iter().any(|file| *file >= 8) {
+            return Err(format!("invalid castling rights {} with rook files {:?}", rights, rook_files));
+        }
+        Ok(Castling { rights, rook_files, chess960 })
+    }
+}
+
+impl Readable for Bitboards {
+    fn to_readable(&self) -> String {
+        write_board(self)
+    }
+
+    fn from_readable(text: &str) -> Result<Self, String> {
+        read_board(text).map_err(|error| error.to_string())
+    }
+}
+
+impl Compact for Bitboards {
+    type Repr = [u64; 12];
+
+    fn to_compact(&self) -> Self::Repr {
+        self.boards.map(Bitboard::to_u64)
+    }
+
+    fn from_compact(boards: Self::Repr) -> Result<Self, String> {
+        let mut bitboards = Bitboards::new_empty();
+        for (piece_nr, board) in boards.into_iter().enumerate() {
+            let piece = PieceIndex::try_from(piece_nr).expect("Bitboards::from_compact: there are 12 pieces");
+            for square in Bitboard::new_const(board) {
+                if bitboards.piece_on_square(square).is_some() {
+                    return Err(format!("two pieces on {}", square.square_str()));
+                }
+                bitboards.set(piece, square);
+            }
+        }
+        Ok(bitboards)
+    }
+}
+
+impl Readable for Snapshot {
+    fn to_readable(&self) -> String {
+        Position { current: *self, history: vec![] }.to_readable()
+    }
+
+    fn from_readable(text: &str) -> Result<Self, String> {
+        Position::from_readable(text).map(|position| position.current)
+    }
+}
+
+impl Compact for Snapshot {
+    // board, side to move (0 is white), castling, en passant square, halfmove clock, fullmove number
+    type Repr = ([u64; 12], u8, (u8, [u8; 4], bool), Option<u8>, u16, u16);
+
+    fn to_compact(&self) -> Self::Repr {
+        let side = if self.side_to_move == Color::White { 0 } else { 1 };
+        (self.bitboards.to_compact(), side, self.castling.to_compact(), self.en_passant.map(|square| square.index()),
+         self.halfmove_clock, self.fullmove_number)
+    }
+
+    fn from_compact((boards, side, castling, en_passant, halfmove_clock, fullmove_number): Self::Repr) -> Result<Self, String> {
+        let side_to_move = match side {
+            0 => Color::White,
+            1 => Color::Black,
+            _ => return Err(format!("{} is not a side to move", side)),
+        };
+        let en_passant = match en_passant {
+            None => None,
+            Some(index) => match Square::from_compact(index)? {
+                // behind a pawn the other side just moved, as in a fen
+                square if square.to_coord().0 == if side_to_move == Color::White { 5 } else { 2 } => Some(square),
+                square => return Err(format!("{} can't be an en passant square", square.square_str())),
+            },
+        };
+        let bitboards = Bitboards::from_compact(boards)?;
+        check_pieces(&bitboards).map_err(|error| error.to_string())?;
+        let mut snapshot = Snapshot {
+            bitboards,
+            side_to_move,
+            castling: Castling::from_compact(castling)?,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+            zobrist_key: 0,
+        };
+        snapshot.zobrist_key = snapshot.compute_key();
+        Ok(snapshot)
+    }
+}
+
+impl Readable for Position {
+    // Shredder-FEN for chess960, so it is still chess960 when read back
+    fn to_readable(&self) -> String {
+        if self.is_chess960() { self.write_shredder_fen() } else { self.write_fen() }
+    }
+
+    fn from_readable(text: &str) -> Result<Self, String> {
+        Position::try_from_fen(text).map_err(|error| error.to_string())
+    }
+}
+
+impl Compact for Position {
+    type Repr = <Snapshot as Compact>::Repr;
+
+    fn to_compact(&self) -> Self::Repr {
+        self.current.to_compact()
+    }
+
+    fn from_compact(repr: Self::Repr) -> Result<Self, String> {
+        Ok(Position { current: Snapshot::from_compact(repr)?, history: vec![] })
+    }
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn json<T: Serialize>(value: &T) -> String {
+        serde_json::to_string(value).unwrap()
+    }
+
+    fn from_json<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+
+    // The same value through the compact form
+    struct CompactForm<T: Compact>(T);
+
+    impl<T: Compact> Serialize for CompactForm<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            compact::serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de, T: Compact> Deserialize<'de> for CompactForm<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            compact::deserialize(deserializer).map(CompactForm)
+        }
+    }
+
+    fn compact_round_trip<T: Compact + PartialEq + std::fmt::Debug>(value: T) -> T {
+        let text = serde_json::to_string(&CompactForm(value)).unwrap();
+        serde_json::from_str::<CompactForm<T>>(&text).unwrap().0
+    }
+
+    const FENS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+    ];
+
+    #[test]
+    fn test_square_and_piece() {
+        assert_eq!(json(&Square::E4), "\"e4\"");
+        assert_eq!(from_json::<Square>("\"h8\"").unwrap(), Square::H8);
+        assert!(from_json::<Square>("\"i9\"").is_err());
+        assert_eq!(json(&PieceIndex::BlackKnight), "\"n\"");
+        assert_eq!(from_json::<PieceIndex>("\"Q\"").unwrap(), PieceIndex::WhiteQueen);
+        assert!(from_json::<PieceIndex>("\"Qq\"").is_err());
+
+        for index in 0..64 {
+            let square = Square::from_idx(index).unwrap();
+            assert_eq!(from_json::<Square>(&json(&square)).unwrap(), square);
+            assert_eq!(compact_round_trip(square), square);
+        }
+        for index in 0..12 {
+            let piece = PieceIndex::try_from(index).unwrap();
+            assert_eq!(from_json::<PieceIndex>(&json(&piece)).unwrap(), piece);
+            assert_eq!(compact_round_trip(piece), piece);
+        }
+        assert_eq!(serde_json::to_string(&CompactForm(Square::E4)).unwrap(), "28");
+        assert!(serde_json::from_str::<CompactForm<Square>>("64").is_err());
+        assert!(serde_json::from_str::<CompactForm<PieceIndex>>("12").is_err());
+    }
+
+    #[test]
+    fn test_moves() {
+        for fen in FENS {
+            let position = Position::new(Some(fen));
+            for mov in position.legal_moves().iter() {
+                let text = json(mov);
+                assert_eq!(text, format!("\"{}\"", mov.to_uci()));
+                // the UCI string only has the squares and promotion, the position has the rest
+                let read: BitMove = from_json(&text).unwrap();
+                assert_eq!(read.to_uci(), mov.to_uci());
+                assert_eq!(position.find_move(read), Some(*mov));
+
+                assert_eq!(compact_round_trip(*mov), *mov);
+            }
+        }
+        assert!(from_json::<BitMove>("\"e7e8k\"").is_err());
+        assert!(from_json::<BitMove>("\"e2\"").is_err());
+    }
+
+    #[test]
+    fn test_positions() {
+        for fen in FENS {
+            let position = Position::new(Some(fen));
+            assert_eq!(json(&position), format!("\"{}\"", fen));
+            assert_eq!(from_json::<Position>(&json(&position)).unwrap(), position);
+            assert_eq!(compact_round_trip(position.clone()), position);
+
+            let snapshot = position.current;
+            assert_eq!(from_json::<Snapshot>(&json(&snapshot)).unwrap(), snapshot);
+            assert_eq!(compact_round_trip(snapshot), snapshot);
+
+            let board = snapshot.bitboards;
+            assert_eq!(json(&board), format!("\"{}\"", fen.split(' ').next().unwrap()));
+            assert_eq!(from_json::<Bitboards>(&json(&board)).unwrap(), board);
+            assert_eq!(compact_round_trip(board), board);
+
+            let castling = snapshot.castling;
+            assert_eq!(from_json::<Castling>(&json(&castling)).unwrap(), castling);
+            assert_eq!(compact_round_trip(castling), castling);
+        }
+
+        // The history is not written
+        let mut played = Position::new(None);
+        played.make_move(played.parse_uci("e2e4").unwrap());
+        let read: Position = from_json(&json(&played)).unwrap();
+        assert_eq!(read.current, played.current);
+        assert!(read.history.is_empty());
+
+        assert_eq!(json(&Position::new(Some(FENS[3])).current.castling), "\"KhQfkhqf\"");
+        assert_eq!(json(&Castling::new()), "\"-\"");
+        assert!(from_json::<Castling>("\"KK\"").is_err());
+        assert!(from_json::<Position>("\"not a fen\"").is_err());
+        // Two pieces on a1
+        assert!(serde_json::from_str::<CompactForm<Bitboards>>("[1,0,0,0,0,0,1,0,0,0,0,0]").is_err());
+        // e3 can only be the en passant square with black to move
+        let mut repr = Position::new(None).current.to_compact();
+        repr.3 = Some(Square::E3.index());
+        assert!(Snapshot::from_compact(repr).is_err());
+        repr.1 = 1;
+        assert_eq!(Snapshot::from_compact(repr).unwrap().en_passant, Some(Square::E3));
+
+        // A white pawn on h8, and no white king
+        let mut repr = Position::new(Some("4k3/8/8/8/8/8/8/4K3 w - - 0 1")).current.to_compact();
+        repr.0[0] |= 1 << 63;
+        assert_eq!(Snapshot::from_compact(repr), Err("there is a pawn on h8, pawns can't be on the first or last rank".to_string()));
+        let mut repr = Position::new(None).current.to_compact();
+        repr.0[PieceIndex::WhiteKing as usize] = 0;
+        assert!(Snapshot::from_compact(repr).is_err());
+    }
+}
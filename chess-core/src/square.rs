@@ -1,8 +1,10 @@
+use std::sync::LazyLock;
+
 use num_enum::TryFromPrimitive;
 use crate::{board::Bitboard, position::Color};
 
 
-#[derive(Copy, Clone, Debug, PartialEq, Default, TryFromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, TryFromPrimitive)]
 #[repr(u8)]
 pub enum Square {
     #[default] // For fast iteration of not needed ellements
@@ -19,24 +21,11 @@ pub enum Square {
 impl std::str::FromStr for Square {
     type Err = String;
 
+    // "e4" or "E4"
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_uppercase().as_str() {
-            "A1" => Ok(Square::A1), "B1" => Ok(Square::B1), "C1" => Ok(Square::C1), "D1" => Ok(Square::D1),
-            "E1" => Ok(Square::E1), "F1" => Ok(Square::F1), "G1" => Ok(Square::G1), "H1" => Ok(Square::H1),
-            "A2" => Ok(Square::A2), "B2" => Ok(Square::B2), "C2" => Ok(Square::C2), "D2" => Ok(Square::D2),
-            "E2" => Ok(Square::E2), "F2" => Ok(Square::F2), "G2" => Ok(Square::G2), "H2" => Ok(Square::H2),
-            "A3" => Ok(Square::A3), "B3" => Ok(Square::B3), "C3" => Ok(Square::C3), "D3" => Ok(Square::D3),
-            "E3" => Ok(Square::E3), "F3" => Ok(Square::F3), "G3" => Ok(Square::G3), "H3" => Ok(Square::H3),
-            "A4" => Ok(Square::A4), "B4" => Ok(Square::B4), "C4" => Ok(Square::C4), "D4" => Ok(Square::D4),
-            "E4" => Ok(Square::E4), "F4" => Ok(Square::F4), "G4" => Ok(Square::G4), "H4" => Ok(Square::H4),
-            "A5" => Ok(Square::A5), "B5" => Ok(Square::B5), "C5" => Ok(Square::C5), "D5" => Ok(Square::D5),
-            "E5" => Ok(Square::E5), "F5" => Ok(Square::F5), "G5" => Ok(Square::G5), "H5" => Ok(Square::H5),
-            "A6" => Ok(Square::A6), "B6" => Ok(Square::B6), "C6" => Ok(Square::C6), "D6" => Ok(Square::D6),
-            "E6" => Ok(Square::E6), "F6" => Ok(Square::F6), "G6" => Ok(Square::G6), "H6" => Ok(Square::H6),
-            "A7" => Ok(Square::A7), "B7" => Ok(Square::B7), "C7" => Ok(Square::C7), "D7" => Ok(Square::D7),
-            "E7" => Ok(Square::E7), "F7" => Ok(Square::F7), "G7" => Ok(Square::G7), "H7" => Ok(Square::H7),
-            "A8" => Ok(Square::A8), "B8" => Ok(Square::B8), "C8" => Ok(Square::C8), "D8" => Ok(Square::D8),
-            "E8" => Ok(Square::E8), "F8" => Ok(Square::F8), "G8" => Ok(Square::G8), "H8" => Ok(Square::H8),
+        let mut chars = s.chars();
+        match (chars.next().and_then(File::from_char), chars.next().and_then(Rank::from_char), chars.next()) {
+            (Some(file), Some(rank), None) => Ok(Square::new(file, rank)),
             _ => Err(format!("Invalid square convertion from: {}", s)),
         }
     }
@@ -45,6 +34,76 @@ impl std::str::FromStr for Square {
 
 
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, TryFromPrimitive)]
+#[repr(u8)]
+pub enum File { A, B, C, D, E, F, G, H }
+
+impl File {
+    pub const ALL: [File; 8] = [File::A, File::B, File::C, File::D, File::E, File::F, File::G, File::H];
+
+    pub fn from_index(idx: usize) -> Option<File> {
+        File::ALL.get(idx).copied()
+    }
+
+    // 'a'..'h' (upper case works too)
+    pub fn from_char(c: char) -> Option<File> {
+        File::from_index((c.to_ascii_lowercase() as usize).checked_sub('a' as usize)?)
+    }
+
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self as u8) as char
+    }
+
+    pub fn to_bitboard(self) -> Bitboard {
+        Bitboard::file(self.index())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Rank { First, Second, Third, Fourth, Fifth, Sixth, Seventh, Eighth }
+
+impl Rank {
+    pub const ALL: [Rank; 8] = [Rank::First, Rank::Second, Rank::Third, Rank::Fourth, Rank::Fifth, Rank::Sixth, Rank::Seventh, Rank::Eighth];
+
+    pub fn from_index(idx: usize) -> Option<Rank> {
+        Rank::ALL.get(idx).copied()
+    }
+
+    // '1'..'8'
+    pub fn from_char(c: char) -> Option<Rank> {
+        Rank::from_index((c as usize).checked_sub('1' as usize)?)
+    }
+
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self as u8) as char
+    }
+
+    pub fn to_bitboard(self) -> Bitboard {
+        Bitboard::rank(self.index())
+    }
+
+    // The rank as seen from that color's side (black's back rank is its first rank)
+    pub fn relative(self, color: Color) -> Rank {
+        match color {
+            Color::White => self,
+            Color::Black => Rank::ALL[7 - self.index()],
+        }
+    }
+}
+
+
+
 impl Square {
 
     pub fn square_str(self) -> String{
@@ -104,6 +163,52 @@ impl Square {
         Bitboard::from(self.index())
     }
 
+    #[inline]
+    pub fn new(file: File, rank: Rank) -> Square {
+        Square::from_coords(rank.index(), file.index()).expect("Square::new: file and rank are always on the board")
+    }
+
+    #[inline]
+    pub fn file(self) -> File {
+        File::ALL[self.index() as usize % 8]
+    }
+
+    #[inline]
+    pub fn rank(self) -> Rank {
+        Rank::ALL[self.index() as usize / 8]
+    }
+
+    pub fn relative_rank(self, color: Color) -> Rank {
+        self.rank().relative(color)
+    }
+
+    // The square `df` files to the right and `dr` ranks up, None if that is off the board
+    pub fn offset(self, df: i8, dr: i8) -> Option<Square> {
+        let col = self.file().index() as i8 + df;
+        let row = self.rank().index() as i8 + dr;
+        if (0..8).contains(&col) && (0..8).contains(&row) {
+            Square::from_coords(row as usize, col as usize)
+        } else {
+            None
+        }
+    }
+
+    // Chebyshev distance, the number of king moves between the squares
+    pub fn distance(self, other: Square) -> u8 {
+        let (df, dr) = self.file_rank_diff(other);
+        df.max(dr)
+    }
+
+    pub fn manhattan_distance(self, other: Square) -> u8 {
+        let (df, dr) = self.file_rank_diff(other);
+        df + dr
+    }
+
+    fn file_rank_diff(self, other: Square) -> (u8, u8) {
+        ((self.file().index() as i8 - other.file().index() as i8).unsigned_abs(),
+         (self.rank().index() as i8 - other.rank().index() as i8).unsigned_abs())
+    }
+
     
 
 }
@@ -112,7 +217,99 @@ impl Square {
 
 
 
+// The squares strictly between a and b if they are on the same rank, file or diagonal, empty if not
+pub fn between(a: Square, b: Square) -> Bitboard {
+    GEOMETRY.between[a.index() as usize][b.index() as usize]
+}
+
+// The whole line (edge to edge) through a and b, including them, empty if they are not on one line
+pub fn line(a: Square, b: Square) -> Bitboard {
+    GEOMETRY.line[a.index() as usize][b.index() as usize]
+}
+
+struct Geometry {
+    between: [[Bitboard; 64]; 64],
+    line: [[Bitboard; 64]; 64],
+}
+
+const DIRECTIONS: [(i8, i8); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+static GEOMETRY: LazyLock<Box<Geometry>> = LazyLock::new(|| {
+    let mut geometry = Box::new(Geometry { between: [[Bitboard::new_empty(); 64]; 64], line: [[Bitboard::new_empty(); 64]; 64] });
+
+    for a_idx in 0..64u8 {
+        let a = Square::from_idx(a_idx).expect("GEOMETRY: index is below 64");
+        for (df, dr) in DIRECTIONS {
+            // The full line through a in this direction, both ways
+            let mut full_line = a.to_bitboard();
+            for sign in [1, -1] {
+                let mut square = a;
+                while let Some(next) = square.offset(df * sign, dr * sign) {
+                    full_line |= next.to_bitboard();
+                    square = next;
+                }
+            }
+
+            let mut between = Bitboard::new_empty();
+            let mut square = a;
+            while let Some(b) = square.offset(df, dr) {
+                geometry.between[a_idx as usize][b.index() as usize] = between;
+                geometry.line[a_idx as usize][b.index() as usize] = full_line;
+                between |= b.to_bitboard();
+                square = b;
+            }
+        }
+    }
+    geometry
+});
+
+
+
+
+
+
 #[test]
 fn test_square(){
     dbg!(Square::A8 as u8);
+}
+
+#[test]
+fn test_square_geometry(){
+    use std::str::FromStr;
+
+    assert_eq!(Square::from_str("e4"), Ok(Square::E4));
+    assert_eq!(Square::from_str("H8"), Ok(Square::H8));
+    assert!(Square::from_str("i1").is_err());
+    assert!(Square::from_str("e9").is_err());
+    assert!(Square::from_str("e44").is_err());
+    for idx in 0..64 {
+        let square = Square::from_idx(idx).unwrap();
+        assert_eq!(Square::from_str(&square.square_str()), Ok(square));
+        assert_eq!(Square::new(square.file(), square.rank()), square);
+    }
+
+    assert_eq!(Square::E4.file(), File::E);
+    assert_eq!(Square::E4.rank(), Rank::Fourth);
+    assert_eq!(File::from_char('c'), Some(File::C));
+    assert_eq!(Rank::from_char('9'), None);
+    assert_eq!(Square::E2.relative_rank(Color::Black), Rank::Seventh);
+    assert_eq!(Square::E7.relative_rank(Color::Black), Rank::Second);
+
+    assert_eq!(Square::E4.offset(1, 2), Some(Square::F6));
+    assert_eq!(Square::H4.offset(1, 0), None);
+    assert_eq!(Square::A1.offset(0, -1), None);
+
+    assert_eq!(Square::A1.distance(Square::H8), 7);
+    assert_eq!(Square::A1.manhattan_distance(Square::H8), 14);
+    assert_eq!(Square::E4.distance(Square::F6), 2);
+    assert_eq!(Square::E4.manhattan_distance(Square::F6), 3);
+
+    assert_eq!(between(Square::A1, Square::D4), Square::B2.to_bitboard() | Square::C3.to_bitboard());
+    assert_eq!(between(Square::D4, Square::A1), between(Square::A1, Square::D4));
+    assert_eq!(between(Square::E1, Square::E3), Square::E2.to_bitboard());
+    assert!(between(Square::E1, Square::E2).is_empty());
+    assert!(between(Square::A1, Square::B3).is_empty());
+    assert_eq!(line(Square::C3, Square::E5), crate::bitboard_consts::DIAG_A1_H8);
+    assert_eq!(line(Square::E2, Square::E7), crate::bitboard_consts::FILE_E);
+    assert!(line(Square::A1, Square::B3).is_empty());
 }
\ No newline at end of file
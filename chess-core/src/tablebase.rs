@@ -0,0 +1,959 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::attack;
+use crate::board::{Bitboard, Bitboards};
+use crate::kastling::Castling;
+use crate::moves::BitMove;
+use crate::piece::{Piece, PieceIndex};
+use crate::position::{Color, Position, Snapshot};
+use crate::square::Square;
+
+
+
+// Endgame tablebases made by retrograde analysis: for every position with a given material the exact result
+// with perfect play and how many plies it takes to mate (DTM). The 50 move rule is not taken into account.
+//
+// A table is generated backwards, ply by ply. First the mates (lost in 0) and stalemates are found, and what
+// the captures and promotions give. Then the moves are taken back from every position settled at ply n - 1:
+// a position that can move to one lost in n - 1 is won in n, and one that moved to a position won in n - 1 is
+// lost in n if every move it has goes to a won position (none longer). Whatever is left at the end is a draw.
+// Captures and promotions lead into smaller tables, which are generated first. En passant right after a double
+// push is not seen while generating, which only matters when both sides have pawns.
+//
+// The index is side to move, white king, black king and then every other piece (64 squares each).
+// The board is mirrored so the white king always stands on a1-d1-d4 (10 squares), with pawns only left to
+// right, so it stands on the a to d files (32 squares). The side with more material is always white in the
+// tables, probing a position where black has more flips the colors.
+
+
+
+// Every piece on the board, kings included. A table grows 64 times for each piece: while generating it takes
+// 3 bytes a position (the value and a count), a 5 piece table about 1 GB without pawns and 3.2 GB with them,
+// and the positions waiting for their ply on top of that. Stored it is 2 bytes a position.
+pub const MAX_PIECES: usize = 5;
+
+// The order the pieces (other than kings) are in, both in names and in the index
+const PIECE_ORDER: [Piece; 5] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn];
+
+const MAGIC: &[u8; 4] = b"CCTB";
+const VERSION: u8 = 1;
+
+// Stored values, seen from the side to move. Higher is better: a faster win is higher and a slower loss too.
+const MATE: i16 = 30000;
+const DRAW: i16 = 0;
+const ILLEGAL: i16 = i16::MIN;
+const UNKNOWN: i16 = i16::MIN + 1; // only while generating
+
+// The white king squares in pawnless tables
+const TRIANGLE: [Square; 10] = [Square::A1, Square::B1, Square::C1, Square::D1, Square::B2, Square::C2, Square::D2, Square::C3, Square::D3, Square::D4];
+
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TbResult {
+    Win(u16),  // the side to move mates in this many plies
+    Draw,
+    Loss(u16), // the side to move is mated in this many plies (0 is mated now)
+}
+
+fn decode_value(value: i16) -> Option<TbResult> {
+    match value {
+        ILLEGAL | UNKNOWN => None,
+        DRAW => Some(TbResult::Draw),
+        value if value > 0 => Some(TbResult::Win((MATE - value) as u16)),
+        value => Some(TbResult::Loss((MATE + value) as u16)),
+    }
+}
+
+fn win(plies: u16) -> i16 {
+    MATE - plies as i16
+}
+
+fn loss(plies: u16) -> i16 {
+    -(MATE - plies as i16)
+}
+
+// The value before the move that led to a position with this value: one ply more, seen from the other side
+fn value_before(value: i16) -> i16 {
+    match value {
+        DRAW => DRAW,
+        value if value > 0 => -value + 1,
+        value => -value - 1,
+    }
+}
+
+
+
+// The pieces each side has besides the king, counted by Piece (pawn, knight, bishop, rook, queen)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Material {
+    counts: [[u8; 5]; 2], // [white, black]
+}
+
+impl Material {
+    pub fn of(bitboards: &Bitboards) -> Self {
+        let mut counts = [[0; 5]; 2];
+        for (side, color) in [Color::White, Color::Black].into_iter().enumerate() {
+            for piece in PIECE_ORDER {
+                counts[side][piece as usize] = bitboards.pieces(piece, color).count() as u8;
+            }
+        }
+        Material { counts }
+    }
+
+    pub fn count(&self, color: Color, piece: Piece) -> u8 {
+        self.counts[color as usize][piece as usize]
+    }
+
+    // Kings included
+    pub fn piece_count(&self) -> usize {
+        2 + self.counts.iter().flatten().map(|count| *count as usize).sum::<usize>()
+    }
+
+    pub fn has_pawns(&self) -> bool {
+        self.counts[0][Piece::Pawn as usize] > 0 || self.counts[1][Piece::Pawn as usize] > 0
+    }
+
+    pub fn flipped(&self) -> Self {
+        Material { counts: [self.counts[1], self.counts[0]] }
+    }
+
+    // White has at least as much as black: more pieces, or as many and the better ones
+    pub fn is_canonical(&self) -> bool {
+        let strength = |side: usize| {
+            let counts = &self.counts[side];
+            (counts.iter().sum::<u8>(), PIECE_ORDER.map(|piece| counts[piece as usize]))
+        };
+        strength(0) >= strength(1)
+    }
+
+    pub fn canonical(&self) -> Self {
+        if self.is_canonical() { *self } else { self.flipped() }
+    }
+
+    // The pieces besides the kings in index order: white's first, each side ordered like PIECE_ORDER
+    fn pieces(&self) -> Vec<PieceIndex> {
+        let mut pieces = Vec::new();
+        for color in [Color::White, Color::Black] {
+            for piece in PIECE_ORDER {
+                for _ in 0..self.count(color, piece) {
+                    pieces.push(PieceIndex::from_piece(piece, color));
+                }
+            }
+        }
+        pieces
+    }
+
+    // Every (canonical) material a capture or promotion can lead to
+    fn successors(&self) -> Vec<Material> {
+        let mut successors = Vec::new();
+        let mut add = |material: Material| {
+            let material = material.canonical();
+            if !successors.contains(&material) {
+                successors.push(material);
+            }
+        };
+
+        for side in 0..2 {
+            for piece in PIECE_ORDER {
+                if self.counts[side][piece as usize] > 0 {
+                    let mut captured = *self;
+                    captured.counts[side][piece as usize] -= 1;
+                    add(captured);
+                }
+            }
+
+            if self.counts[side][Piece::Pawn as usize] > 0 {
+                for promotion in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+                    let mut promoted = *self;
+                    promoted.counts[side][Piece::Pawn as usize] -= 1;
+                    promoted.counts[side][promotion as usize] += 1;
+                    add(promoted);
+
+                    // a pawn that captures as it promotes
+                    for piece in PIECE_ORDER {
+                        if promoted.counts[1 - side][piece as usize] > 0 {
+                            let mut captured = promoted;
+                            captured.counts[1 - side][piece as usize] -= 1;
+                            add(captured);
+                        }
+                    }
+                }
+            }
+        }
+        successors
+    }
+}
+
+impl fmt::Display for Material {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (side, color) in [Color::White, Color::Black].into_iter().enumerate() {
+            if side == 1 {
+                write!(f, "v")?;
+            }
+            write!(f, "K")?;
+            for piece in PIECE_ORDER {
+                let letter = PieceIndex::from_piece(piece, Color::White).to_fen_char();
+                for _ in 0..self.count(color, piece) {
+                    write!(f, "{}", letter)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// "KQvK", "KRPvKR", the 'v' can be left out ("KQK")
+impl FromStr for Material {
+    type Err = TablebaseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let invalid = || TablebaseError::InvalidMaterial(name.to_string());
+        let name_without_v = name.replace('v', "");
+        let mut sides = name_without_v.split('K').skip(1);
+        let (Some(white), Some(black), None) = (sides.next(), sides.next(), sides.next()) else {
+            return Err(invalid());
+        };
+        if !name_without_v.starts_with('K') {
+            return Err(invalid());
+        }
+
+        let mut counts = [[0u8; 5]; 2];
+        for (side, letters) in [white, black].into_iter().enumerate() {
+            for letter in letters.chars() {
+                let piece = match letter {
+                    'Q' => Piece::Queen,
+                    'R' => Piece::Rook,
+                    'B' => Piece::Bishop,
+                    'N' => Piece::Knight,
+                    'P' => Piece::Pawn,
+                    _ => return Err(invalid()),
+                };
+                let count = &mut counts[side][piece as usize];
+                *count = count.checked_add(1).ok_or_else(invalid)?;
+            }
+        }
+        Ok(Material { counts })
+    }
+}
+
+
+
+// Mirrors that keep a position the same (with pawns only the left to right one)
+#[derive(Copy, Clone)]
+struct Symmetry {
+    flip_file: bool,
+    flip_rank: bool,
+    transpose: bool, // mirror in the a1-h8 diagonal
+}
+
+impl Symmetry {
+    // The mirror that puts the white king in its part of the board
+    fn for_king(king: Square, has_pawns: bool) -> Self {
+        let (row, col) = king.to_coord();
+        if has_pawns {
+            return Symmetry { flip_file: col > 3, flip_rank: false, transpose: false };
+        }
+        let flip_file = col > 3;
+        let flip_rank = row > 3;
+        let (row, col) = (if flip_rank { 7 - row } else { row }, if flip_file { 7 - col } else { col });
+        Symmetry { flip_file, flip_rank, transpose: row > col }
+    }
+
+    fn apply(self, square: Square) -> Square {
+        let (mut row, mut col) = square.to_coord();
+        if self.flip_file {
+            col = 7 - col;
+        }
+        if self.flip_rank {
+            row = 7 - row;
+        }
+        if self.transpose {
+            (row, col) = (col, row);
+        }
+        Square::from_coords(row, col).expect("Symmetry::apply: a mirrored square is on the board")
+    }
+
+    fn apply_to_board(self, bitboards: &Bitboards) -> Bitboards {
+        let mut mirrored = Bitboards::new_empty();
+        for square in bitboards.all_occupancy.iter() {
+            let piece = bitboards.piece_on_square(square).expect("Symmetry::apply_to_board: the square is occupied");
+            mirrored.set(piece, self.apply(square));
+        }
+        mirrored
+    }
+}
+
+// The squares a pawn of `color` can have been pushed to `square` from, stopped by any piece in the way
+fn pawn_origins(square: Square, occupancy: Bitboard, color: Color) -> Bitboard {
+    let row = if color == Color::White { square.to_coord().0 } else { 7 - square.to_coord().0 };
+    let step = if color == Color::White { -1 } else { 1 };
+    let mut origins = Bitboard::new_empty();
+    if row >= 2 && let Some(one) = square.offset(0, step).filter(|one| !occupancy.contains(*one)) {
+        origins |= one.to_bitboard();
+        if row == 3 && let Some(two) = one.offset(0, step).filter(|two| !occupancy.contains(*two)) {
+            origins |= two.to_bitboard();
+        }
+    }
+    origins
+}
+
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    material: Material,
+    values: Vec<i16>,
+}
+
+impl Table {
+    pub fn material(&self) -> Material {
+        self.material
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn king_squares(&self) -> usize {
+        if self.material.has_pawns() { 32 } else { TRIANGLE.len() }
+    }
+
+    fn size(material: Material) -> usize {
+        let kings = if material.has_pawns() { 32 } else { TRIANGLE.len() };
+        2 * kings * 64usize.pow(material.piece_count() as u32 - 1)
+    }
+
+    // The index of a position with the table's material or the same material with the colors swapped
+    fn index(&self, snapshot: &Snapshot) -> usize {
+        let flip_colors = Material::of(&snapshot.bitboards) != self.material;
+        let color_of = |color: Color| if flip_colors { !color } else { color };
+        let square_of = |square: Square| if flip_colors {
+            Square::from_coords(7 - square.to_coord().0, square.to_coord().1).expect("Table::index: a flipped square is on the board")
+        } else {
+            square
+        };
+
+        let king = |color: Color| {
+            let king = snapshot.bitboards.pieces(Piece::King, color_of(color)).lsb().expect("Table::index: both kings are on the board");
+            square_of(king)
+        };
+        let symmetry = Symmetry::for_king(king(Color::White), self.material.has_pawns());
+        let white_king = symmetry.apply(king(Color::White));
+
+        let king_index = if self.material.has_pawns() {
+            white_king.to_coord().0 * 4 + white_king.to_coord().1
+        } else {
+            TRIANGLE.iter().position(|square| *square == white_king).expect("Table::index: the mirrored king is in the triangle")
+        };
+        let side = color_of(snapshot.side_to_move) as usize;
+        let mut index = (side * self.king_squares() + king_index) * 64 + symmetry.apply(king(Color::Black)).index() as usize;
+
+        for color in [Color::White, Color::Black] {
+            for piece in PIECE_ORDER {
+                // The same pieces are sorted (a bitboard goes from a1 to h8), so they only have one index
+                let squares: Bitboard = snapshot.bitboards.pieces(piece, color_of(color)).iter()
+                    .map(|square| symmetry.apply(square_of(square)))
+                    .collect();
+                for square in squares {
+                    index = index * 64 + square.index() as usize;
+                }
+            }
+        }
+        index
+    }
+
+    // The position at the index, None if the pieces can't stand like that or the index is not the one index()
+    // gives for it (same pieces in the wrong order)
+    fn position(&self, index: usize) -> Option<Position> {
+        let pieces = self.material.pieces();
+        let mut rest = index;
+        let mut squares = vec![0usize; pieces.len()];
+        for square in squares.iter_mut().rev() {
+            *square = rest % 64;
+            rest /= 64;
+        }
+        let black_king = rest % 64;
+        rest /= 64;
+        let king_index = rest % self.king_squares();
+        let side = rest / self.king_squares();
+
+        let white_king = if self.material.has_pawns() {
+            Square::from_coords(king_index / 4, king_index % 4)?
+        } else {
+            TRIANGLE[king_index]
+        };
+
+        let mut bitboards = Bitboards::new_empty();
+        let all = std::iter::once((PieceIndex::WhiteKing, white_king.index() as usize))
+            .chain(std::iter::once((PieceIndex::BlackKing, black_king)))
+            .chain(pieces.iter().copied().zip(squares.iter().copied()));
+        for (piece, square_index) in all {
+            let square = Square::from_idx(square_index as u8)?;
+            if bitboards.piece_on_square(square).is_some() {
+                return None;
+            }
+            if Piece::from_piece_index(&piece) == Piece::Pawn && matches!(square.to_coord().0, 0 | 7) {
+                return None;
+            }
+            bitboards.set(piece, square);
+        }
+        if pieces.windows(2).zip(squares.windows(2)).any(|(piece, square)| piece[0] == piece[1] && square[0] >= square[1]) {
+            return None;
+        }
+
+        let side_to_move = if side == 0 { Color::White } else { Color::Black };
+        let current = Snapshot {
+            bitboards, side_to_move, castling: Castling::new(), en_passant: None, halfmove_clock: 0, fullmove_number: 1, zobrist_key: 0,
+        };
+        let mut position = Position { current, history: vec![] };
+        position.current.zobrist_key = position.current.compute_key();
+
+        // The side that is not to move can't be in check
+        let other_king = position.king_square(!side_to_move).expect("Table::position: both kings were placed");
+        if position.is_square_attacked(other_king, side_to_move) {
+            return None;
+        }
+        Some(position)
+    }
+
+    fn value(&self, snapshot: &Snapshot) -> i16 {
+        self.values[self.index(snapshot)]
+    }
+
+    // The indices of the positions one move (no capture or promotion) before this one. A pawnless position
+    // with the white king on the a1-h8 diagonal has a second index, mirrored in the diagonal, so both are given.
+    fn predecessors(&self, position: &Position) -> Vec<usize> {
+        let current = &position.current;
+        let mover = !current.side_to_move;
+        let occupancy = current.bitboards.all_occupancy;
+        let transpose = Symmetry { flip_file: false, flip_rank: false, transpose: true };
+
+        let mut indices = Vec::new();
+        for square in current.bitboards.occupancy(mover).iter() {
+            let piece = current.bitboards.piece_on_square(square).expect("Table::predecessors: the square is occupied");
+            let origins = if piece.to_piece() == Piece::Pawn {
+                pawn_origins(square, occupancy, mover)
+            } else {
+                attack::get_attacks(piece, square, occupancy, mover) & !occupancy
+            };
+
+            for origin in origins.iter() {
+                let mut before = *current;
+                before.bitboards.remove(piece, square);
+                before.bitboards.set(piece, origin);
+                before.side_to_move = mover;
+                let index = self.index(&before);
+                if self.values[index] == ILLEGAL {
+                    continue; // the side that moved would have been in check
+                }
+                indices.push(index);
+                if !self.material.has_pawns() {
+                    before.bitboards = transpose.apply_to_board(&before.bitboards);
+                    indices.push(self.index(&before));
+                }
+            }
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+
+    // The file format: "CCTB", version, the length of the material name, the name ("KQvK"),
+    // the number of values (u64) and then every value (i16), numbers little endian
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), TablebaseError> {
+        let name = self.material.to_string();
+        let mut bytes = Vec::with_capacity(16 + name.len() + 2 * self.values.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&(self.values.len() as u64).to_le_bytes());
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        writer.write_all(&bytes).map_err(|error| TablebaseError::Io(error.to_string()))
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, TablebaseError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|error| TablebaseError::Io(error.to_string()))?;
+        let invalid = |reason: &str| TablebaseError::InvalidFile(reason.to_string());
+
+        if bytes.len() < 6 || &bytes[0..4] != MAGIC {
+            return Err(invalid("not a tablebase file"));
+        }
+        if bytes[4] != VERSION {
+            return Err(invalid("unknown version"));
+        }
+        let name_end = 6 + bytes[5] as usize;
+        let name = bytes.get(6..name_end).and_then(|name| std::str::from_utf8(name).ok()).ok_or_else(|| invalid("broken material name"))?;
+        let material: Material = name.parse()?;
+        // The table size would overflow with too many pieces
+        if material.piece_count() > MAX_PIECES {
+            return Err(TablebaseError::TooManyPieces(name.to_string()));
+        }
+
+        let count = bytes.get(name_end..name_end + 8).ok_or_else(|| invalid("missing value count"))?;
+        let count = u64::from_le_bytes(count.try_into().expect("Table::read: 8 bytes")) as usize;
+        let data = &bytes[name_end + 8..];
+        if count != Table::size(material) || data.len() != 2 * count {
+            return Err(invalid("wrong number of values"));
+        }
+
+        let values = data.chunks_exact(2).map(|value| i16::from_le_bytes([value[0], value[1]])).collect();
+        Ok(Table { material, values })
+    }
+}
+
+
+
+#[derive(Clone, Debug, Default)]
+pub struct Tablebase {
+    tables: HashMap<Material, Table>,
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Tablebase { tables: HashMap::new() }
+    }
+
+    pub fn table(&self, material: Material) -> Option<&Table> {
+        self.tables.get(&material.canonical())
+    }
+
+    pub fn add_table(&mut self, table: Table) {
+        self.tables.insert(table.material, table);
+    }
+
+    // Generates the table and every smaller table it needs, tables that are already there are not made again
+    pub fn generate(&mut self, material: Material) -> Result<(), TablebaseError> {
+        let material = material.canonical();
+        if self.tables.contains_key(&material) {
+            return Ok(());
+        }
+        if material.piece_count() > MAX_PIECES {
+            return Err(TablebaseError::TooManyPieces(material.to_string()));
+        }
+
+        for successor in material.successors() {
+            self.generate(successor)?;
+        }
+        let table = self.solve(material);
+        self.tables.insert(material, table);
+        Ok(())
+    }
+
+    fn solve(&self, material: Material) -> Table {
+        let mut table = Table { material, values: vec![UNKNOWN; Table::size(material)] };
+        // The positions to settle at each ply: lost at the even plies and won at the odd ones
+        let mut plies: Vec<Vec<usize>> = vec![Vec::new()];
+        // How many of the positions in this table a position can move to are not won yet, so the moves are
+        // only looked at again when they might all be. Mirrored indices can count one too many, never too few.
+        let mut waiting = vec![0u8; table.len()];
+        fn settle_at(plies: &mut Vec<Vec<usize>>, ply: usize, index: usize) {
+            if plies.len() <= ply {
+                plies.resize(ply + 1, Vec::new());
+            }
+            plies[ply].push(index);
+        }
+
+        // Mates, stalemates and what the captures and promotions give, which are known from the smaller tables
+        for (index, waiting) in waiting.iter_mut().enumerate() {
+            let Some(mut position) = table.position(index) else {
+                table.values[index] = ILLEGAL;
+                continue;
+            };
+
+            let moves = position.legal_moves();
+            if moves.size() == 0 {
+                if position.is_in_check() {
+                    settle_at(&mut plies, 0, index);
+                } else {
+                    table.values[index] = DRAW;
+                }
+                continue;
+            }
+
+            let mut best = None;
+            let mut next = Vec::new();
+            for mov in moves.iter() {
+                position.make_move(*mov);
+                if !mov.is_capture() && mov.get_premotion_piece().is_none() {
+                    next.push(table.index(&position.current));
+                } else {
+                    let value = self.value(&position.current).expect("Tablebase::solve: the smaller tables were generated first");
+                    best = best.max(Some(value_before(value)));
+                }
+                position.unmake_move().expect("Tablebase::solve: unmake_move after make_move should always work");
+            }
+            next.sort_unstable();
+            next.dedup();
+            *waiting = next.len() as u8;
+
+            match best {
+                Some(value) if value > DRAW => settle_at(&mut plies, (MATE - value) as usize, index),
+                Some(value) if value < DRAW && next.is_empty() => settle_at(&mut plies, (MATE + value) as usize, index),
+                _ => (),
+            }
+        }
+
+        // Backwards from the positions settled at each ply: a move into a lost position wins, and a position
+        // is lost when every move goes to a won position, the last of them settled at this ply
+        let mut ply = 0;
+        while ply < plies.len() {
+            let mut settled = std::mem::take(&mut plies[ply]);
+            settled.sort_unstable();
+            settled.dedup();
+            let value = if ply % 2 == 0 { loss(ply as u16) } else { win(ply as u16) };
+
+            for index in settled {
+                if table.values[index] == UNKNOWN {
+                    table.values[index] = value;
+                } else if table.values[index] != value {
+                    continue; // won faster
+                }
+                let position = table.position(index).expect("Tablebase::solve: a settled position is legal");
+                for before in table.predecessors(&position) {
+                    if table.values[before] != UNKNOWN {
+                        continue;
+                    }
+                    if value < DRAW {
+                        settle_at(&mut plies, ply + 1, before);
+                        continue;
+                    }
+                    waiting[before] = waiting[before].saturating_sub(1);
+                    // Lost one ply after the longest win, which can be a capture that only comes later
+                    if waiting[before] == 0 && let Some(longest) = self.longest_win_after(&table, before) {
+                        settle_at(&mut plies, longest + 1, before);
+                    }
+                }
+            }
+            ply += 1;
+        }
+
+        for value in table.values.iter_mut().filter(|value| **value == UNKNOWN) {
+            *value = DRAW;
+        }
+        table
+    }
+
+    // The longest win the other side gets after a move, None if a move keeps a draw or wins or goes to a
+    // position in the table that is not settled yet
+    fn longest_win_after(&self, table: &Table, index: usize) -> Option<usize> {
+        let mut position = table.position(index)?;
+        let mut longest = 0;
+        for mov in position.legal_moves().iter() {
+            position.make_move(*mov);
+            let value = if !mov.is_capture() && mov.get_premotion_piece().is_none() {
+                table.value(&position.current)
+            } else {
+                self.value(&position.current).expect("Tablebase::longest_win_after: the smaller tables were generated first")
+            };
+            position.unmake_move().expect("Tablebase::longest_win_after: unmake_move after make_move should always work");
+
+            if value <= DRAW {
+                return None;
+            }
+            longest = longest.max((MATE - value) as usize);
+        }
+        Some(longest)
+    }
+
+    fn value(&self, snapshot: &Snapshot) -> Option<i16> {
+        let material = Material::of(&snapshot.bitboards);
+        let table = self.tables.get(&material.canonical())?;
+        Some(table.value(snapshot))
+    }
+
+    // The result for the side to move. None when there is no table for the material or the position can still castle.
+    pub fn probe(&self, position: &Position) -> Option<TbResult> {
+        self.probe_value(position).and_then(decode_value)
+    }
+
+    fn probe_value(&self, position: &Position) -> Option<i16> {
+        if position.current.castling.rights != 0 {
+            return None;
+        }
+        // En passant is not in the tables, so look one move ahead
+        if position.current.en_passant.is_some() && position.legal_moves().iter().any(|mov| mov.is_en_passant() && mov.is_capture()) {
+            return self.search(position).map(|(_, value)| value);
+        }
+        self.value(&position.current).filter(|value| *value != ILLEGAL)
+    }
+
+    // The best move and the value of the position (from the values after every move)
+    fn search(&self, position: &Position) -> Option<(Option<BitMove>, i16)> {
+        let moves = position.legal_moves();
+        if moves.size() == 0 {
+            return Some((None, if position.is_in_check() { loss(0) } else { DRAW }));
+        }
+
+        let mut best: Option<(BitMove, i16)> = None;
+        let mut child = position.clone();
+        for mov in moves.iter() {
+            child.make_move(*mov);
+            let value = value_before(self.probe_value(&child)?);
+            child.unmake_move().expect("Tablebase::search: unmake_move after make_move should always work");
+            if best.is_none_or(|(_, best_value)| value > best_value) {
+                best = Some((*mov, value));
+            }
+        }
+        best.map(|(mov, value)| (Some(mov), value))
+    }
+
+    // The move that wins fastest, loses slowest or keeps the draw
+    pub fn best_move(&self, position: &Position) -> Option<BitMove> {
+        if position.current.castling.rights != 0 {
+            return None;
+        }
+        self.search(position).and_then(|(mov, _)| mov)
+    }
+
+    // Writes every table to "<material>.cctb" in the directory
+    pub fn save<P: AsRef<Path>>(&self, directory: P) -> Result<(), TablebaseError> {
+        for table in self.tables.values() {
+            let path = directory.as_ref().join(format!("{}.cctb", table.material));
+            let file = std::fs::File::create(path).map_err(|error| TablebaseError::Io(error.to_string()))?;
+            table.write(std::io::BufWriter::new(file))?;
+        }
+        Ok(())
+    }
+
+    // Loads every .cctb file in the directory
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<Self, TablebaseError> {
+        let mut tablebase = Tablebase::new();
+        let entries = std::fs::read_dir(directory).map_err(|error| TablebaseError::Io(error.to_string()))?;
+        for entry in entries {
+            let path = entry.map_err(|error| TablebaseError::Io(error.to_string()))?.path();
+            if path.extension().is_some_and(|extension| extension == "cctb") {
+                let file = std::fs::File::open(&path).map_err(|error| TablebaseError::Io(error.to_string()))?;
+                tablebase.add_table(Table::read(std::io::BufReader::new(file))?);
+            }
+        }
+        Ok(tablebase)
+    }
+}
+
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TablebaseError {
+    Io(String),
+    InvalidMaterial(String),
+    TooManyPieces(String),
+    InvalidFile(String),
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TablebaseError::Io(message) => write!(f, "could not read or write the table: {}", message),
+            TablebaseError::InvalidMaterial(name) => write!(f, "'{}' is not a material like KQvK", name),
+            TablebaseError::TooManyPieces(name) => write!(f, "{} has more than {} pieces", name, MAX_PIECES),
+            TablebaseError::InvalidFile(reason) => write!(f, "invalid tablebase file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for TablebaseError {}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::LazyLock;
+    use rand::Rng;
+
+    // KPvK needs every other 3 piece table, so they are all made once for every test
+    static TABLES: LazyLock<Tablebase> = LazyLock::new(|| {
+        let mut tablebase = Tablebase::new();
+        tablebase.generate("KPvK".parse().unwrap()).unwrap();
+        tablebase
+    });
+
+    fn probe(fen: &str) -> Option<TbResult> {
+        TABLES.probe(&Position::new(Some(fen)))
+    }
+
+    // Can the side to move mate within `plies` plies (odd)
+    fn mates_within(position: &mut Position, plies: u16) -> bool {
+        let moves = position.legal_moves();
+        moves.iter().any(|mov| {
+            position.make_move(*mov);
+            let replies = position.legal_moves();
+            let mates = if replies.size() == 0 {
+                position.is_in_check()
+            } else if plies < 3 {
+                false
+            } else {
+                replies.iter().all(|reply| {
+                    position.make_move(*reply);
+                    let mates = mates_within(position, plies - 2);
+                    position.unmake_move().unwrap();
+                    mates
+                })
+            };
+            position.unmake_move().unwrap();
+            mates
+        })
+    }
+
+    #[test]
+    fn test_material() {
+        let material: Material = "KRPvKR".parse().unwrap();
+        assert_eq!(material.to_string(), "KRPvKR");
+        assert_eq!(material.piece_count(), 5);
+        assert!(material.has_pawns());
+        assert_eq!("KQK".parse::<Material>().unwrap().to_string(), "KQvK");
+        assert!(!"KvKQ".parse::<Material>().unwrap().is_canonical());
+        assert_eq!("KvKQ".parse::<Material>().unwrap().canonical().to_string(), "KQvK");
+        assert!("KXvK".parse::<Material>().is_err());
+        assert!("QvK".parse::<Material>().is_err());
+        let too_many = format!("K{}vK", "Q".repeat(300));
+        assert_eq!(too_many.parse::<Material>(), Err(TablebaseError::InvalidMaterial(too_many.clone())));
+
+        let successors: Vec<String> = "KPvK".parse::<Material>().unwrap().successors().iter().map(|material| material.to_string()).collect();
+        assert_eq!(successors, ["KvK", "KNvK", "KBvK", "KRvK", "KQvK"]);
+
+        let mut tablebase = Tablebase::new();
+        assert_eq!(tablebase.generate("KQRBvKN".parse().unwrap()), Err(TablebaseError::TooManyPieces("KQRBvKN".to_string())));
+    }
+
+    #[test]
+    fn test_probe() {
+        // The longest mates: 10 moves with the queen and 16 with the rook
+        let longest = |name: &str| TABLES.table(name.parse().unwrap()).unwrap().values.iter()
+            .filter_map(|value| match decode_value(*value) { Some(TbResult::Win(plies)) => Some(plies), _ => None })
+            .max();
+        assert_eq!(longest("KQvK"), Some(19));
+        assert_eq!(longest("KRvK"), Some(31));
+        assert_eq!(longest("KBvK"), None);
+
+        assert_eq!(probe("k7/7Q/1K6/8/8/8/8/8 w - - 0 1"), Some(TbResult::Win(1)));
+        assert_eq!(probe("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"), Some(TbResult::Loss(0)));
+        // Black has the queen, so the colors are flipped for the table
+        assert_eq!(probe("K7/7q/1k6/8/8/8/8/8 b - - 0 1"), Some(TbResult::Win(1)));
+        // Stalemate, and a rook that is taken
+        assert_eq!(probe("k7/8/1Q6/8/8/8/8/2K5 b - - 0 1"), Some(TbResult::Draw));
+        assert_eq!(probe("8/8/8/8/8/8/6Rk/4K3 b - - 0 1"), Some(TbResult::Draw));
+        // Opposition: white wins only if black has to move
+        assert_eq!(probe("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"), Some(TbResult::Draw));
+        assert!(matches!(probe("8/8/8/4k3/8/4K3/4P3/8 b - - 0 1"), Some(TbResult::Loss(_))));
+        // A rook pawn with the king in the corner is a draw
+        assert_eq!(probe("7k/8/8/8/7P/8/8/6K1 w - - 0 1"), Some(TbResult::Draw));
+
+        // No table, or castling rights
+        assert_eq!(probe("4k3/8/8/8/8/8/8/R3K2R w - - 0 1"), None);
+        assert_eq!(probe("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"), None);
+    }
+
+    #[test]
+    fn test_best_move() {
+        // Following the best moves mates in exactly as many plies as the table says
+        let mut position = Position::new(Some("8/8/8/3k4/8/8/8/K6R w - - 0 1"));
+        let Some(TbResult::Win(plies)) = TABLES.probe(&position) else { panic!("KRvK is won") };
+        for _ in 0..plies {
+            let mov = TABLES.best_move(&position).unwrap();
+            position.make_move(mov);
+        }
+        assert_eq!(TABLES.probe(&position), Some(TbResult::Loss(0)));
+        assert!(position.legal_moves().size() == 0 && position.is_in_check());
+    }
+
+    #[test]
+    fn test_against_mate_search() {
+        let mut rng = rand::rng();
+        for name in ["KQvK", "KRvK", "KPvK"] {
+            let table = TABLES.table(name.parse().unwrap()).unwrap();
+            let mut checked = 0;
+            while checked < 20 { // the search is slow in debug builds
+                let index = rng.random_range(0..table.len());
+                let (Some(mut position), Some(result)) = (table.position(index), decode_value(table.values[index])) else {
+                    continue;
+                };
+                let mate_in_3 = mates_within(&mut position, 3);
+                match result {
+                    TbResult::Win(plies) if plies <= 3 => {
+                        assert!(mate_in_3, "{} {}", name, position.write_fen());
+                        assert_eq!(mates_within(&mut position, 1), plies == 1, "{} {}", name, position.write_fen());
+                    },
+                    _ => assert!(!mate_in_3, "{} {}: {:?}", name, position.write_fen(), result),
+                }
+                checked += 1;
+            }
+        }
+    }
+
+    // Too slow in debug builds: cargo test --release tablebase
+    #[test]
+    #[cfg_attr(debug_assertions, ignore)]
+    fn test_four_pieces() {
+        let mut tablebase = Tablebase::new();
+        tablebase.generate("KRvKN".parse().unwrap()).unwrap();
+        let table = tablebase.table("KRvKN".parse().unwrap()).unwrap();
+
+        // The longest win is 40 moves
+        let longest = table.values.iter()
+            .filter_map(|value| match decode_value(*value) { Some(TbResult::Win(plies)) => Some(plies), _ => None })
+            .max();
+        assert_eq!(longest, Some(79));
+
+        // The knight next to its king holds, but not when it is cut off in a corner
+        assert_eq!(tablebase.probe(&Position::new(Some("8/8/8/3nk3/8/8/8/R3K3 w - - 0 1"))), Some(TbResult::Draw));
+        assert!(matches!(tablebase.probe(&Position::new(Some("n7/8/2K5/8/8/8/3k4/7R w - - 0 1"))), Some(TbResult::Win(_))));
+
+        // The best moves mate as fast as the table says, also when the rook takes the knight on the way
+        let mut rng = rand::rng();
+        let mut checked = 0;
+        while checked < 20 {
+            let index = rng.random_range(0..table.len());
+            let (Some(mut position), Some(TbResult::Win(plies))) = (table.position(index), decode_value(table.values[index])) else {
+                continue;
+            };
+            for _ in 0..plies {
+                position.make_move(tablebase.best_move(&position).unwrap());
+            }
+            assert!(position.legal_moves().size() == 0 && position.is_in_check(), "{}", position.write_fen());
+            checked += 1;
+        }
+    }
+
+    #[test]
+    fn test_table_files() {
+        let table = TABLES.table("KRvK".parse().unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        table.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 6 + 4 + 8 + 2 * table.len());
+        assert_eq!(&Table::read(bytes.as_slice()).unwrap(), table);
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(Table::read(bytes.as_slice()), Err(TablebaseError::InvalidFile(_))));
+        assert!(matches!(Table::read(&b"nope"[..]), Err(TablebaseError::InvalidFile(_))));
+
+        // A material name with too many pieces is turned down before the table is sized
+        let mut header = b"CCTB\x01\x0cKQQQQQQQQQvK".to_vec();
+        header.extend_from_slice(&0u64.to_le_bytes());
+        assert_eq!(Table::read(header.as_slice()), Err(TablebaseError::TooManyPieces("KQQQQQQQQQvK".to_string())));
+
+        let directory = std::env::temp_dir().join(format!("chess-core-tablebase-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        TABLES.save(&directory).unwrap();
+        let loaded = Tablebase::load(&directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(loaded.probe(&Position::new(Some("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"))), Some(TbResult::Draw));
+        assert_eq!(loaded.table("KQvK".parse().unwrap()), TABLES.table("KQvK".parse().unwrap()));
+    }
+}
@@ -0,0 +1,58 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::position::Position;
+use crate::square::Square;
+
+
+
+// Helpers for the tests in the other modules.
+// Random tests get a seeded rng, the seed is printed (cargo shows it when the test fails) and
+// CHESS_TEST_SEED=<seed> runs them again with the same moves.
+
+
+
+pub(crate) fn test_rng() -> StdRng {
+    let seed = match std::env::var("CHESS_TEST_SEED") {
+        Ok(seed) => seed.parse().expect("CHESS_TEST_SEED must be a number"),
+        Err(_) => rand::rng().random(),
+    };
+    eprintln!("random seed {} (CHESS_TEST_SEED={} to run it again)", seed, seed);
+    StdRng::seed_from_u64(seed)
+}
+
+// Prints the position when a check fails in it
+struct ShowOnPanic<'a>(&'a Position);
+
+impl Drop for ShowOnPanic<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!("failed in {}", self.0.write_fen());
+        }
+    }
+}
+
+// Plays up to `plies` random legal moves, calling `check` on the position before the first move and after every move
+pub(crate) fn random_walk(position: &mut Position, plies: usize, rng: &mut StdRng, mut check: impl FnMut(&Position)) {
+    for ply in 0..=plies {
+        let shown = ShowOnPanic(position);
+        check(shown.0);
+        drop(shown);
+
+        let moves = position.legal_moves();
+        if ply == plies || moves.size() == 0 {
+            break;
+        }
+        position.make_move(*moves.get(rng.random_range(0..moves.size())).expect("random_walk: the index is below the size"));
+    }
+}
+
+// Plays the moves given by their squares, panics if one is not legal
+pub(crate) fn play(position: &mut Position, moves: &[(Square, Square)]) {
+    for (from, to) in moves {
+        let mov = position.legal_moves().iter().copied()
+            .find(|mov| mov.get_start_square() == *from && mov.get_end_square() == *to)
+            .unwrap_or_else(|| panic!("{}{} is not legal in {}", from.square_str(), to.square_str(), position.write_fen()));
+        position.make_move(mov);
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+
+use crate::fen_string::FenError;
+use crate::moves::{BitMove, MoveList};
+use crate::piece::{Piece, PieceIndex};
+use crate::position::{Color, Position};
+use crate::square::Square;
+
+
+
+// UCI (long algebraic) moves: start square, end square and a lower case promotion piece, "e2e4", "e7e8q".
+// Castling is written as the king move, "e1g1".
+
+
+
+impl BitMove {
+    pub fn to_uci(&self) -> String {
+        let mut uci = self.get_start_square().square_str();
+        uci.push_str(&self.get_end_square().square_str());
+        if let Some(piece) = self.get_premotion_piece() {
+            uci.push(PieceIndex::from_piece(piece, Color::Black).to_fen_char()); // lower case
+        }
+        uci
+    }
+}
+
+
+
+impl Position {
+    // Finds the legal move the UCI string means, so all the flags (capture, en passant, castling...) are right
+    pub fn parse_uci(&self, uci: &str) -> Result<BitMove, UciError> {
+        let uci_trimmed = uci.trim();
+        if !uci_trimmed.is_ascii() || !(4..=5).contains(&uci_trimmed.len()) {
+            return Err(UciError::InvalidSyntax(uci.to_string()));
+        }
+
+        let from = uci_trimmed[0..2].parse::<Square>().map_err(|_| UciError::InvalidSyntax(uci.to_string()))?;
+        let to = uci_trimmed[2..4].parse::<Square>().map_err(|_| UciError::InvalidSyntax(uci.to_string()))?;
+        let promotion = match uci_trimmed.chars().nth(4) {
+            None => None,
+            Some('q') => Some(Piece::Queen),
+            Some('r') => Some(Piece::Rook),
+            Some('b') => Some(Piece::Bishop),
+            Some('n') => Some(Piece::Knight),
+            Some(_) => return Err(UciError::InvalidSyntax(uci.to_string()))
+        };
+
+        let mut legal = MoveList::new_empty();
+        self.fill_legal(&mut legal);
+
+        legal.iter().copied()
+            .find(|mov| mov.get_start_square() == from && mov.get_end_square() == to && mov.get_premotion_piece() == promotion)
+            .ok_or_else(|| UciError::IllegalMove(uci.to_string()))
+    }
+
+    // The legal move with the same squares and promotion, for moves where only those are known (read from a UCI string)
+    pub fn find_move(&self, mov: BitMove) -> Option<BitMove> {
+        self.parse_uci(&mov.to_uci()).ok()
+    }
+
+    // Plays the moves from the fen, like "position fen ... moves e2e4 e7e5" in UCI
+    pub fn from_uci_moves(start_fen: &str, moves: &[&str]) -> Result<Position, UciError> {
+        let mut position = Position::try_from_fen(start_fen).map_err(UciError::InvalidFen)?;
+        for uci in moves {
+            let mov = position.parse_uci(uci)?;
+            position.make_move(mov);
+        }
+        Ok(position)
+    }
+}
+
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UciError {
+    InvalidFen(FenError),
+    InvalidSyntax(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciError::InvalidFen(error) => write!(f, "invalid start position: {}", error),
+            UciError::InvalidSyntax(uci) => write!(f, "'{}' is not a UCI move (like e2e4 or e7e8q)", uci),
+            UciError::IllegalMove(uci) => write!(f, "'{}' is not a legal move in this position", uci),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kastling::Imposter;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn test_uci_round_trip() {
+        for fen in [START,
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1"] {
+            let position = Position::new(Some(fen));
+            for mov in position.legal_moves().iter() {
+                assert_eq!(position.parse_uci(&mov.to_uci()), Ok(*mov));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_uci_flags() {
+        let position = Position::new(None);
+        let double_push = position.parse_uci("e2e4").unwrap();
+        assert!(double_push.is_double_pawn_push());
+        assert_eq!(double_push.to_uci(), "e2e4");
+
+        let castle = Position::new(Some("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        assert_eq!(castle.parse_uci("e1g1").unwrap().get_castle_side(), Some(Imposter::King));
+        assert_eq!(castle.parse_uci("e1c1").unwrap().get_castle_side(), Some(Imposter::Queen));
+
+        let en_passant = Position::new(Some("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"));
+        assert!(en_passant.parse_uci("e5d6").unwrap().is_en_passant());
+
+        let promo = Position::new(Some("3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1"));
+        let capture_promo = promo.parse_uci("e7d8n").unwrap();
+        assert!(capture_promo.is_capture());
+        assert_eq!(capture_promo.get_premotion_piece(), Some(Piece::Knight));
+        assert_eq!(capture_promo.to_uci(), "e7d8n");
+    }
+
+    #[test]
+    fn test_parse_uci_errors() {
+        let position = Position::new(None);
+        assert_eq!(position.parse_uci("e2e5"), Err(UciError::IllegalMove("e2e5".to_string())));
+        assert_eq!(position.parse_uci("e1g1"), Err(UciError::IllegalMove("e1g1".to_string())));
+        assert_eq!(position.parse_uci("e2"), Err(UciError::InvalidSyntax("e2".to_string())));
+        assert_eq!(position.parse_uci("e2e4x"), Err(UciError::InvalidSyntax("e2e4x".to_string())));
+        assert_eq!(position.parse_uci("i2e4"), Err(UciError::InvalidSyntax("i2e4".to_string())));
+
+        let promo = Position::new(Some("3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(promo.parse_uci("e7e8"), Err(UciError::IllegalMove("e7e8".to_string()))); // must say what to promote to
+    }
+
+    #[test]
+    fn test_from_uci_moves() {
+        let position = Position::from_uci_moves(START, &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "g8f6", "e1g1"]).unwrap();
+        assert_eq!(position.write_fen(), "r1bqkb1r/pppp1ppp/2n2n2/1B2p3/4P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4");
+        assert_eq!(position.history.len(), 7);
+
+        assert_eq!(Position::from_uci_moves(START, &["e2e4", "e2e4"]), Err(UciError::IllegalMove("e2e4".to_string())));
+        assert!(matches!(Position::from_uci_moves("not a fen", &[]), Err(UciError::InvalidFen(_))));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::attack;
+use crate::board::Bitboards;
+use crate::piece::{Piece, PieceIndex};
+use crate::position::{Color, Snapshot};
+use crate::square::Square;
+
+
+
+// Zobrist hashing: every (piece, square), the side to move, every castling rights combination and every
+// en passant file gets a random u64. The key of a position is all the numbers that are "on" xored together,
+// so a move only has to xor out what disappears and xor in what appears.
+
+
+
+// splitmix64, so the tables can be made at compile time and are the same on every run (important for saved hashes)
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const SEED: u64 = 0x5EED_C4E5_5B0A_4D00;
+
+struct Tables {
+    piece_square: [[u64; 64]; 12],
+    castling: [u64; 16], // indexed by Castling::rights
+    en_passant_file: [u64; 8],
+    side_to_move: u64,
+}
+
+const fn make_tables() -> Tables {
+    let mut tables = Tables {
+        piece_square: [[0; 64]; 12],
+        castling: [0; 16],
+        en_passant_file: [0; 8],
+        side_to_move: 0,
+    };
+    let mut state = SEED;
+
+    let mut piece = 0;
+    while piece < 12 {
+        let mut square = 0;
+        while square < 64 {
+            let (next_state, value) = splitmix64(state);
+            state = next_state;
+            tables.piece_square[piece][square] = value;
+            square += 1;
+        }
+        piece += 1;
+    }
+
+    let mut rights = 0;
+    while rights < 16 {
+        let (next_state, value) = splitmix64(state);
+        state = next_state;
+        tables.castling[rights] = value;
+        rights += 1;
+    }
+
+    let mut file = 0;
+    while file < 8 {
+        let (next_state, value) = splitmix64(state);
+        state = next_state;
+        tables.en_passant_file[file] = value;
+        file += 1;
+    }
+
+    tables.side_to_move = splitmix64(state).1;
+    tables
+}
+
+static TABLES: Tables = make_tables();
+
+
+
+#[inline]
+pub fn piece_square(piece: PieceIndex, square: Square) -> u64 {
+    TABLES.piece_square[piece.index()][square.index() as usize]
+}
+
+#[inline]
+pub fn castling(rights: u8) -> u64 {
+    TABLES.castling[(rights & 0b1111) as usize]
+}
+
+#[inline]
+pub fn en_passant(square: Option<Square>) -> u64 {
+    match square {
+        Some(square) => TABLES.en_passant_file[square.to_coord().1],
+        None => 0
+    }
+}
+
+// Xored in when it is black to move
+#[inline]
+pub fn side_to_move() -> u64 {
+    TABLES.side_to_move
+}
+
+pub fn pieces_key(bitboards: &Bitboards) -> u64 {
+    let mut key = 0;
+    for piece_nr in 0..12 {
+        let piece = PieceIndex::try_from(piece_nr).expect("pieces_key: piece number outside of PieceIndex");
+        let mut board = bitboards.get_bitboard(piece);
+        while let Some(idx) = board.pop_lsb() {
+            key ^= piece_square(piece, Square::from_idx(idx).expect("pieces_key: index outside the board"));
+        }
+    }
+    key
+}
+
+
+
+impl Snapshot {
+    // The incrementally updated key
+    #[inline]
+    pub fn key(&self) -> u64 {
+        self.zobrist_key
+    }
+
+    // Computes the key from nothing (used when reading fen and to check the incremental key when debugging)
+    pub fn compute_key(&self) -> u64 {
+        let mut key = pieces_key(&self.bitboards);
+        key ^= castling(self.castling.rights);
+        key ^= self.en_passant_key();
+        if self.side_to_move == Color::Black {
+            key ^= side_to_move();
+        }
+        key
+    }
+
+    // The en passant file is only part of the key when a pawn can actually take en passant, otherwise the
+    // position is the same as without the en passant square (matters for repetitions)
+    #[inline]
+    pub fn en_passant_key(&self) -> u64 {
+        match self.en_passant {
+            Some(square) => {
+                let my_pawns = self.bitboards.get_bitboard(PieceIndex::from_piece(Piece::Pawn, self.side_to_move));
+                if attack::pawn_attacks(square, !self.side_to_move).intersects(my_pawns) {
+                    en_passant(Some(square))
+                } else {
+                    0
+                }
+            },
+            None => 0
+        }
+    }
+
+    // Puts a piece on the board and updates the key
+    #[inline]
+    pub fn set_piece(&mut self, piece: PieceIndex, square: Square) {
+        self.bitboards.set(piece, square);
+        self.zobrist_key ^= piece_square(piece, square);
+    }
+
+    // Removes a piece from the board and updates the key
+    #[inline]
+    pub fn remove_piece(&mut self, piece: PieceIndex, square: Square) {
+        self.bitboards.remove(piece, square);
+        self.zobrist_key ^= piece_square(piece, square);
+    }
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use crate::position::Position;
+    use crate::square::Square;
+    use crate::test_util::{play, random_walk, test_rng};
+
+    #[test]
+    fn test_incremental_key_matches_recompute() {
+        let mut rng = test_rng();
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"] {
+            let mut position = Position::new(Some(fen));
+            random_walk(&mut position, 100, &mut rng, |position| {
+                assert_eq!(position.current.key(), position.current.compute_key());
+            });
+        }
+    }
+
+    #[test]
+    fn test_transposition_same_key() {
+        let mut a = Position::new(None);
+        let mut b = Position::new(None);
+        play(&mut a, &[(Square::G1, Square::F3), (Square::G8, Square::F6), (Square::B1, Square::C3), (Square::B8, Square::C6)]);
+        play(&mut b, &[(Square::B1, Square::C3), (Square::B8, Square::C6), (Square::G1, Square::F3), (Square::G8, Square::F6)]);
+        assert_eq!(a.current.key(), b.current.key());
+        assert_ne!(a.current.key(), Position::new(None).current.key());
+
+        // Knights going back and forth gives the same position (and key) again
+        play(&mut a, &[(Square::F3, Square::G1), (Square::F6, Square::G8), (Square::G1, Square::F3), (Square::G8, Square::F6)]);
+        assert_eq!(a.current.key(), b.current.key());
+
+        // Only the side to move differs
+        let black_to_move = Position::new(Some("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1"));
+        assert_ne!(black_to_move.current.key(), Position::new(None).current.key());
+    }
+}